@@ -1,7 +1,7 @@
 use egui::{Align2, Context, Painter, Shape};
 use walkers::{
-    extras::{Image, Images, Place, Places, Style, Texture},
-    Map, MapMemory, Plugin, Projector, Tiles,
+    extras::{Compass, Image, Images, Place, Places, Style, Texture},
+    Layer, Map, MapMemory, Plugin, Projector, Tiles, ZoomAnchor,
 };
 
 pub struct ImageHandler {
@@ -16,6 +16,7 @@ pub struct MyApp {
     geoportal_tiles: Tiles,
     map_memory: MapMemory,
     satellite: bool,
+    satellite_opacity: f32,
     image: ImageHandler,
 }
 
@@ -39,6 +40,7 @@ impl MyApp {
             geoportal_tiles: Tiles::new(walkers::providers::Geoportal, egui_ctx),
             map_memory: MapMemory::default(),
             satellite: false,
+            satellite_opacity: 1.0,
             image,
         }
     }
@@ -57,17 +59,23 @@ impl eframe::App for MyApp {
                 // Typically this would be a GPS acquired position which is tracked by the map.
                 let my_position = places::wroclaw_glowny();
 
-                // Select either OSM standard map or satellite.
-                let tiles = if self.satellite {
-                    &mut self.geoportal_tiles
+                let status = if self.satellite {
+                    self.geoportal_tiles.status()
                 } else {
-                    &mut self.tiles
+                    self.tiles.status()
                 };
 
-                let attribution = tiles.attribution();
-
                 // In egui, widgets are constructed and consumed in each frame.
-                let map = Map::new(Some(tiles), &mut self.map_memory, my_position);
+                let map = Map::new(Some(&mut self.tiles), &mut self.map_memory, my_position)
+                    .with_zoom_anchor(ZoomAnchor::Pointer)
+                    .with_attribution(Align2::LEFT_TOP);
+
+                // Satellite imagery can be shown over the OSM standard map.
+                let map = map.with_layer(
+                    Layer::new(&mut self.geoportal_tiles)
+                        .with_opacity(self.satellite_opacity)
+                        .with_visible(self.satellite),
+                );
 
                 // Optionally, a plugin which draw custom stuff on the map can be attached.
                 let map = map
@@ -88,7 +96,8 @@ impl eframe::App for MyApp {
                     .with_plugin(Images::new(vec![Image {
                         position: places::wroclavia(),
                         texture: self.image.texture.clone(),
-                    }]));
+                    }]))
+                    .with_plugin(Compass::new(Align2::RIGHT_CENTER));
                 // Draw the map widget.
                 ui.add(map);
 
@@ -98,8 +107,13 @@ impl eframe::App for MyApp {
 
                     zoom(ui, &mut self.map_memory);
                     go_to_my_position(ui, &mut self.map_memory);
-                    controls(ui, &mut self.satellite, &mut self.image);
-                    acknowledge(ui, &attribution);
+                    controls(
+                        ui,
+                        &mut self.satellite,
+                        &mut self.satellite_opacity,
+                        &mut self.image,
+                    );
+                    loading(ui, &status);
                 }
             });
     }
@@ -169,20 +183,34 @@ impl Plugin for CustomShapes {
 mod windows {
     use super::ImageHandler;
     use egui::{Align2, RichText, Ui, Window};
-    use walkers::{providers::Attribution, Center, MapMemory};
+    use walkers::{Center, MapMemory, Status};
+
+    /// Show whether tiles are still being downloaded, or there are some problems with it.
+    pub fn loading(ui: &Ui, status: &Status) {
+        let text = if status.in_flight > 0 {
+            format!("loading {} tiles…", status.in_flight)
+        } else if let Some(error) = &status.last_error {
+            format!("could not load some tiles: {}", error)
+        } else {
+            return;
+        };
 
-    pub fn acknowledge(ui: &Ui, attribution: &Attribution) {
-        Window::new("Acknowledge")
+        Window::new("Loading")
             .collapsible(false)
             .resizable(false)
             .title_bar(false)
-            .anchor(Align2::LEFT_TOP, [10., 10.])
+            .anchor(Align2::CENTER_BOTTOM, [0., -10.])
             .show(ui.ctx(), |ui| {
-                ui.hyperlink_to(attribution.text, attribution.url);
+                ui.label(text);
             });
     }
 
-    pub fn controls(ui: &Ui, satellite: &mut bool, image: &mut ImageHandler) {
+    pub fn controls(
+        ui: &Ui,
+        satellite: &mut bool,
+        satellite_opacity: &mut f32,
+        image: &mut ImageHandler,
+    ) {
         Window::new("Satellite")
             .collapsible(false)
             .resizable(false)
@@ -191,6 +219,10 @@ mod windows {
             .fixed_size([150., 150.])
             .show(ui.ctx(), |ui| {
                 ui.checkbox(satellite, "satellite view");
+                ui.add_enabled(
+                    *satellite,
+                    egui::Slider::new(satellite_opacity, 0.0..=1.0).text("Opacity"),
+                );
                 ui.add(egui::Slider::new(&mut image.angle, 0.0..=360.0).text("Rotate"));
                 ui.add(egui::Slider::new(&mut image.x_scale, 0.1..=3.0).text("Scale width"));
                 ui.add(egui::Slider::new(&mut image.y_scale, 0.1..=3.0).text("Scale heigth"));
@@ -0,0 +1,253 @@
+//! In-memory cache with the least-recently-used eviction policy.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// Limit of the in-memory tile cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capacity {
+    /// Maximum number of tiles.
+    Tiles(usize),
+
+    /// Maximum size of the decoded images, in bytes.
+    Bytes(usize),
+}
+
+/// Statistics of the in-memory tile cache, see [`crate::Tiles::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of times a tile was found in the cache.
+    pub hits: u64,
+
+    /// Number of times a tile was not (yet) available.
+    pub misses: u64,
+
+    /// Number of tiles removed from the cache to keep it within the [`Capacity`].
+    pub evictions: u64,
+
+    /// Number of entries currently in the cache, including the ones still being downloaded.
+    pub tiles: usize,
+
+    /// Size of the decoded images currently in the cache.
+    pub bytes: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    last_used: u64,
+}
+
+pub(crate) struct Lru<K, V> {
+    capacity: Option<Capacity>,
+    entries: HashMap<K, Entry<V>>,
+
+    /// Keys ordered by their last use.
+    order: BTreeMap<u64, K>,
+    clock: u64,
+    size: usize,
+    evictions: u64,
+}
+
+impl<K, V> Lru<K, V>
+where
+    K: Hash + Eq + Copy,
+{
+    pub fn new(capacity: Option<Capacity>) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+            size: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Get the value, marking it as recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.get_mut(key).map(|value| &*value)
+    }
+
+    /// Same as [`Lru::get`], but the value can be modified. Its size stays the same.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.last_used);
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.order.insert(self.clock, *key);
+        Some(&mut entry.value)
+    }
+
+    /// Get the value without affecting its order.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    #[cfg(test)]
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Insert the value of given size (which should be in bytes), evicting least recently used
+    /// entries if the capacity got exceeded.
+    pub fn insert(&mut self, key: K, value: V, size: usize) {
+        self.remove(&key);
+
+        self.clock += 1;
+        self.size += size;
+        self.order.insert(self.clock, key);
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                last_used: self.clock,
+            },
+        );
+
+        self.evict();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.last_used);
+        self.size -= entry.size;
+        Some(entry.value)
+    }
+
+    /// Remove entries for which `f` returns `false`. These are not counted as evictions.
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        let order = &mut self.order;
+        let size = &mut self.size;
+        self.entries.retain(|key, entry| {
+            let keep = f(key, &entry.value);
+            if !keep {
+                order.remove(&entry.last_used);
+                *size -= entry.size;
+            }
+            keep
+        });
+    }
+
+    /// Iterate over the values, without affecting their order.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values().map(|entry| &entry.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Total size of the entries.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of entries evicted so far.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    fn exceeded(&self) -> bool {
+        match self.capacity {
+            None => false,
+            Some(Capacity::Tiles(tiles)) => self.entries.len() > tiles,
+            Some(Capacity::Bytes(bytes)) => self.size > bytes,
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.exceeded() {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&key) {
+                self.size -= entry.size;
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_cache_never_evicts() {
+        let mut lru = Lru::new(None);
+
+        for key in 0..100 {
+            lru.insert(key, (), 1);
+        }
+
+        assert_eq!(100, lru.len());
+        assert_eq!(100, lru.size());
+        assert_eq!(0, lru.evictions());
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted_when_tile_count_is_exceeded() {
+        let mut lru = Lru::new(Some(Capacity::Tiles(2)));
+
+        lru.insert(1, "one", 0);
+        lru.insert(2, "two", 0);
+
+        // Use the first one, so the second becomes the least recently used.
+        assert_eq!(Some(&"one"), lru.get(&1));
+        lru.insert(3, "three", 0);
+
+        assert!(lru.contains(&1));
+        assert!(!lru.contains(&2));
+        assert!(lru.contains(&3));
+        assert_eq!(1, lru.evictions());
+    }
+
+    #[test]
+    fn entries_are_evicted_when_size_is_exceeded() {
+        let mut lru = Lru::new(Some(Capacity::Bytes(10)));
+
+        lru.insert(1, (), 4);
+        lru.insert(2, (), 4);
+        assert_eq!(8, lru.size());
+
+        lru.insert(3, (), 6);
+        assert_eq!(10, lru.size());
+        assert_eq!(2, lru.len());
+        assert!(!lru.contains(&1));
+
+        // Replacing an entry does not count as an eviction.
+        lru.insert(3, (), 2);
+        assert_eq!(6, lru.size());
+        assert_eq!(1, lru.evictions());
+    }
+
+    #[test]
+    fn retaining_entries() {
+        let mut lru = Lru::new(Some(Capacity::Tiles(3)));
+
+        lru.insert(1, "odd", 1);
+        lru.insert(2, "even", 2);
+        lru.insert(3, "odd", 3);
+
+        lru.retain(|_, value| *value == "odd");
+        assert_eq!(2, lru.len());
+        assert_eq!(4, lru.size());
+        assert_eq!(0, lru.evictions());
+
+        // Removed entry must not be picked for eviction.
+        lru.insert(4, "even", 4);
+        lru.insert(5, "odd", 5);
+        assert!(!lru.contains(&1));
+        assert!(lru.contains(&3));
+        assert_eq!(vec!["even", "odd", "odd"], {
+            let mut values: Vec<_> = lru.values().copied().collect();
+            values.sort();
+            values
+        });
+    }
+}
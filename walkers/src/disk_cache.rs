@@ -0,0 +1,446 @@
+//! Persistent, on-disk cache of the tiles, so they survive between sessions and can be used
+//! offline.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{http_cache::CachePolicy, mercator::TileId};
+
+/// Extension of the files stored in the cache. Tiles are kept exactly as they were downloaded,
+/// so they might be PNGs, JPEGs or anything else the tile server sends.
+const EXTENSION: &str = "tile";
+
+/// Extension of the files keeping tiles' [`CachePolicy`].
+const POLICY_EXTENSION: &str = "policy";
+
+/// Configuration of the on-disk tile cache. Tiles are stored in `{zoom}/{x}/{y}.tile` files
+/// inside the given directory, along with `{y}.policy` files holding their HTTP caching headers.
+///
+/// Each tile source should use its own directory, as tiles are identified only by their
+/// coordinates.
+///
+/// High resolution tiles, used on HiDPI screens, are kept in a subdirectory, such as `@2x`, which
+/// has its own size limit.
+#[derive(Clone, Debug)]
+pub struct DiskCache {
+    path: PathBuf,
+    max_size: u64,
+}
+
+impl DiskCache {
+    /// Cache tiles in the given directory. It will be created if it does not exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_size: 512 * 1024 * 1024,
+        }
+    }
+
+    /// Maximum size (in bytes) of all the tiles stored in the cache, including their caching
+    /// policies. When it gets exceeded, least recently used tiles are removed. Default is 512 MiB.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Cache of the tiles with given scale factor.
+    pub(crate) fn for_scale(&self, scale: u32) -> Self {
+        if scale > 1 {
+            Self {
+                path: self.path.join(format!("@{}x", scale)),
+                max_size: self.max_size,
+            }
+        } else {
+            self.clone()
+        }
+    }
+}
+
+struct Entry {
+    /// Size of both, tile and its policy.
+    size: u64,
+    policy_size: u64,
+    last_used: u64,
+}
+
+/// Cache opened by the IO task.
+pub(crate) struct Store {
+    root: PathBuf,
+    max_size: u64,
+    entries: HashMap<TileId, Entry>,
+
+    /// Tiles ordered by their last use, so the least recently used one is found quickly.
+    order: BTreeMap<u64, TileId>,
+    size: u64,
+
+    /// Monotonic counter used to order the entries by their last use.
+    clock: u64,
+}
+
+impl Store {
+    /// Open the cache, indexing tiles which are already there. Tiles from previous sessions are
+    /// ordered by their modification time, which gets updated each time a tile is used.
+    pub fn open(config: DiskCache) -> io::Result<Self> {
+        fs::create_dir_all(&config.path)?;
+
+        let mut found = Vec::new();
+        scan(&config.path, &mut found)?;
+        found.sort_by_key(|(_, _, _, modified)| *modified);
+
+        let mut store = Self {
+            root: config.path,
+            max_size: config.max_size,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            size: 0,
+            clock: 0,
+        };
+
+        for (tile_id, size, policy_size, _) in found {
+            store.insert_entry(tile_id, size, policy_size);
+        }
+
+        store.evict();
+        Ok(store)
+    }
+
+    /// Get the tile's bytes, and its caching policy if it is in the cache. Tile is returned even
+    /// if it has already expired.
+    pub fn get(&mut self, tile_id: TileId) -> Option<(Vec<u8>, CachePolicy)> {
+        if !self.entries.contains_key(&tile_id) {
+            return None;
+        }
+
+        let path = self.path(tile_id);
+        match fs::read(&path) {
+            Ok(bytes) => {
+                self.clock += 1;
+                if let Some(entry) = self.entries.get_mut(&tile_id) {
+                    self.order.remove(&entry.last_used);
+                    entry.last_used = self.clock;
+                    self.order.insert(self.clock, tile_id);
+                }
+
+                // Best effort, it only affects the eviction order in the next session.
+                let _ = File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+
+                let policy = fs::read_to_string(path.with_extension(POLICY_EXTENSION))
+                    .map(|policy| CachePolicy::deserialize(&policy))
+                    .unwrap_or_default();
+
+                Some((bytes, policy))
+            }
+            Err(e) => {
+                log::warn!(
+                    "Could not read {} from the disk cache: {}",
+                    path.display(),
+                    e
+                );
+                self.remove(tile_id);
+                None
+            }
+        }
+    }
+
+    /// Put the tile into the cache, evicting least recently used ones if it grew too big.
+    pub fn put(&mut self, tile_id: TileId, bytes: &[u8], policy: &CachePolicy) -> io::Result<()> {
+        let path = self.path(tile_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Policy goes first. If the tile itself fails to be written, it will not be found anyway.
+        let policy_size = self.write_policy_file(tile_id, policy)?;
+
+        // Write into a temporary file first, so a half-written tile never gets read back.
+        let temporary = path.with_extension("part");
+        fs::write(&temporary, bytes)?;
+        fs::rename(&temporary, &path)?;
+
+        self.forget(tile_id);
+        self.insert_entry(tile_id, bytes.len() as u64 + policy_size, policy_size);
+        self.evict();
+        Ok(())
+    }
+
+    /// Replace the caching policy of a tile which is already in the cache, e.g. after it got
+    /// revalidated.
+    pub fn write_policy(&mut self, tile_id: TileId, policy: &CachePolicy) -> io::Result<()> {
+        let policy_size = self.write_policy_file(tile_id, policy)?;
+        if let Some(entry) = self.entries.get_mut(&tile_id) {
+            self.size = self.size - entry.policy_size + policy_size;
+            entry.size = entry.size - entry.policy_size + policy_size;
+            entry.policy_size = policy_size;
+        }
+        Ok(())
+    }
+
+    /// Returns the size of the written file.
+    fn write_policy_file(&self, tile_id: TileId, policy: &CachePolicy) -> io::Result<u64> {
+        let path = self.path(tile_id).with_extension(POLICY_EXTENSION);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let policy = policy.serialize();
+        fs::write(path, &policy)?;
+        Ok(policy.len() as u64)
+    }
+
+    /// Total size of the cached tiles, in bytes.
+    #[cfg(test)]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn insert_entry(&mut self, tile_id: TileId, size: u64, policy_size: u64) {
+        self.clock += 1;
+        self.size += size;
+        self.order.insert(self.clock, tile_id);
+        self.entries.insert(
+            tile_id,
+            Entry {
+                size,
+                policy_size,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Remove the tile from the index, but not from the disk.
+    fn forget(&mut self, tile_id: TileId) -> bool {
+        let Some(entry) = self.entries.remove(&tile_id) else {
+            return false;
+        };
+        self.order.remove(&entry.last_used);
+        self.size -= entry.size;
+        true
+    }
+
+    fn remove(&mut self, tile_id: TileId) {
+        if self.forget(tile_id) {
+            let path = self.path(tile_id);
+            for path in [path.clone(), path.with_extension(POLICY_EXTENSION)] {
+                if let Err(e) = fs::remove_file(&path) {
+                    log::debug!("Could not remove {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((_, &tile_id)) = self.order.first_key_value() else {
+                break;
+            };
+
+            log::debug!("Evicting {:?} from the disk cache.", tile_id);
+            self.remove(tile_id);
+        }
+    }
+
+    fn path(&self, tile_id: TileId) -> PathBuf {
+        self.root
+            .join(tile_id.zoom.to_string())
+            .join(tile_id.x.to_string())
+            .join(format!("{}.{}", tile_id.y, EXTENSION))
+    }
+}
+
+/// [`Store`] which is opened, i.e. indexed, only when first used, as it takes a while for big
+/// caches.
+pub(crate) enum LazyStore {
+    Closed(DiskCache),
+    Open(Store),
+
+    /// There is no disk cache, or it could not be opened.
+    Unavailable,
+}
+
+impl LazyStore {
+    pub fn new(config: Option<DiskCache>) -> Self {
+        config.map_or(Self::Unavailable, Self::Closed)
+    }
+
+    pub fn open(&mut self) -> Option<&mut Store> {
+        if let Self::Closed(config) = self {
+            *self = match Store::open(config.clone()) {
+                Ok(store) => Self::Open(store),
+                Err(e) => {
+                    log::warn!("Could not open the disk cache: {}", e);
+                    Self::Unavailable
+                }
+            };
+        }
+
+        match self {
+            Self::Open(store) => Some(store),
+            Self::Closed(_) | Self::Unavailable => None,
+        }
+    }
+}
+
+/// Find all tiles in the `{zoom}/{x}/{y}.tile` layout.
+/// Sizes include the `{y}.policy` files.
+fn scan(root: &Path, found: &mut Vec<(TileId, u64, u64, SystemTime)>) -> io::Result<()> {
+    for zoom in fs::read_dir(root)? {
+        let zoom = zoom?;
+        let Some(zoom_number) = parse_name(&zoom.path()) else {
+            continue;
+        };
+
+        for x in fs::read_dir(zoom.path())? {
+            let x = x?;
+            let Some(x_number) = parse_name(&x.path()) else {
+                continue;
+            };
+
+            for y in fs::read_dir(x.path())? {
+                let y = y?;
+                let path = y.path();
+
+                if path.extension().and_then(|extension| extension.to_str()) != Some(EXTENSION) {
+                    continue;
+                }
+
+                let (Some(y_number), Ok(metadata)) = (
+                    path.file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .and_then(|stem| stem.parse().ok()),
+                    y.metadata(),
+                ) else {
+                    continue;
+                };
+
+                let policy_size = fs::metadata(path.with_extension(POLICY_EXTENSION))
+                    .map_or(0, |metadata| metadata.len());
+
+                found.push((
+                    TileId {
+                        x: x_number,
+                        y: y_number,
+                        zoom: zoom_number,
+                    },
+                    metadata.len() + policy_size,
+                    policy_size,
+                    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_name<T: std::str::FromStr>(path: &Path) -> Option<T> {
+    if !path.is_dir() {
+        return None;
+    }
+    path.file_name()?.to_str()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile_id(x: u32) -> TileId {
+        TileId { x, y: 2, zoom: 3 }
+    }
+
+    #[test]
+    fn tiles_are_stored_and_read_back() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut store = Store::open(DiskCache::new(directory.path())).unwrap();
+
+        assert_eq!(None, store.get(tile_id(1)));
+        store
+            .put(tile_id(1), b"tile", &CachePolicy::default())
+            .unwrap();
+        assert_eq!(
+            Some((b"tile".to_vec(), CachePolicy::default())),
+            store.get(tile_id(1))
+        );
+        assert!(directory.path().join("3/1/2.tile").exists());
+    }
+
+    #[test]
+    fn existing_tiles_are_found_when_opening() {
+        let directory = tempfile::tempdir().unwrap();
+
+        let policy = CachePolicy {
+            expires: Some(123),
+            etag: Some("\"abc\"".to_owned()),
+            ..Default::default()
+        };
+
+        let mut store = Store::open(DiskCache::new(directory.path())).unwrap();
+        store.put(tile_id(1), b"tile", &policy).unwrap();
+        drop(store);
+
+        let mut store = Store::open(DiskCache::new(directory.path())).unwrap();
+        assert_eq!(4 + policy.serialize().len() as u64, store.size());
+        assert_eq!(
+            Some((b"tile".to_vec(), policy.clone())),
+            store.get(tile_id(1))
+        );
+
+        // Policy can be updated, e.g. after revalidation.
+        let policy = CachePolicy {
+            expires: Some(456),
+            ..policy
+        };
+        store.write_policy(tile_id(1), &policy).unwrap();
+        assert_eq!(Some((b"tile".to_vec(), policy)), store.get(tile_id(1)));
+    }
+
+    #[test]
+    fn least_recently_used_tiles_are_evicted() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut store = Store::open(DiskCache::new(directory.path()).with_max_size(8)).unwrap();
+
+        let policy = CachePolicy::default();
+        store.put(tile_id(1), b"tile", &policy).unwrap();
+        store.put(tile_id(2), b"tile", &policy).unwrap();
+
+        // Use the first one, so the second one becomes the least recently used.
+        assert!(store.get(tile_id(1)).is_some());
+        store.put(tile_id(3), b"tile", &policy).unwrap();
+
+        assert_eq!(8, store.size());
+        assert!(store.get(tile_id(1)).is_some());
+        assert!(store.get(tile_id(2)).is_none());
+        assert!(store.get(tile_id(3)).is_some());
+        assert!(!directory.path().join("3/2/2.tile").exists());
+        assert!(!directory.path().join("3/2/2.policy").exists());
+    }
+
+    #[test]
+    fn policies_count_towards_the_size() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut store = Store::open(DiskCache::new(directory.path()).with_max_size(20)).unwrap();
+
+        let policy = CachePolicy {
+            etag: Some("\"abc\"".to_owned()),
+            ..Default::default()
+        };
+        store.put(tile_id(1), b"tile", &policy).unwrap();
+        assert_eq!(14, store.size());
+
+        store.put(tile_id(2), b"tile", &policy).unwrap();
+        assert_eq!(14, store.size());
+        assert!(store.get(tile_id(1)).is_none());
+
+        // Revalidation might change the size too.
+        store
+            .write_policy(tile_id(2), &CachePolicy::default())
+            .unwrap();
+        assert_eq!(4, store.size());
+    }
+}
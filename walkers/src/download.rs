@@ -1,8 +1,123 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use egui::Context;
-use futures::StreamExt;
-use reqwest::header::USER_AGENT;
+use futures::{future::Either, SinkExt, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{
+    disk_cache::{DiskCache, LazyStore},
+    grid::TileGrid,
+    http_cache::{now, CachePolicy, MIN_FRESHNESS},
+    io::{blocking, sleep},
+    mercator::TileId,
+    providers::{Attribution, FetchError, TileData, TileFetcher, TileFuture, TileSource},
+    tiles::{Format, Tile},
+    TilesOptions,
+};
+
+/// How failed downloads are retried. Only transient failures, such as connection errors, server
+/// errors (5xx) or rate limiting (429) are retried, with exponentially growing delay between the
+/// attempts.
+#[derive(Clone, Debug)]
+pub struct Retry {
+    /// Maximum number of retries, after which the tile is considered failed.
+    pub max_retries: u32,
+
+    /// Delay before the first retry. Each next one waits twice as long.
+    pub initial_backoff: Duration,
+
+    /// Upper limit of the delay between retries.
+    pub max_backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Retry {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+/// Configuration of the HTTP client used to download the tiles.
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+    /// Value of the `User-Agent` header. Some tile servers, like OpenStreetMap's, require it to
+    /// identify the application.
+    pub user_agent: String,
+
+    /// Additional headers sent with each request, e.g. `Authorization`.
+    pub headers: Vec<(String, String)>,
+
+    /// Timeout of a single request, not counting the retries. Not supported on the web.
+    pub timeout: Option<Duration>,
+
+    /// URL of the proxy all requests go through, e.g. `http://proxy.example.com:8080`. Not
+    /// supported on the web, where browser's settings are used.
+    pub proxy: Option<String>,
+
+    /// Maximum number of redirects followed for a single request. Not supported on the web.
+    pub max_redirects: usize,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: "Walkers".to_owned(),
+            headers: Vec::new(),
+            timeout: None,
+            proxy: None,
+            max_redirects: 10,
+        }
+    }
+}
+
+impl HttpOptions {
+    fn client(&self) -> Result<reqwest::Client, Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::Client(format!("{}: {}", name, e)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| Error::Client(format!("{}: {}", name, e)))?;
+            headers.append(name, value);
+        }
 
-use crate::{mercator::TileId, providers::TileSource, tiles::Tile};
+        let builder = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .default_headers(headers);
+
+        #[cfg(not(target_family = "wasm"))]
+        let builder = {
+            let mut builder =
+                builder.redirect(reqwest::redirect::Policy::limited(self.max_redirects));
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(proxy) = &self.proxy {
+                let proxy = reqwest::Proxy::all(proxy)
+                    .map_err(|e| Error::Client(format!("proxy: {}", e)))?;
+                builder = builder.proxy(proxy);
+            }
+            builder
+        };
+
+        builder.build().map_err(|e| Error::Client(e.to_string()))
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 enum Error {
@@ -11,69 +126,551 @@ enum Error {
 
     #[error("error while decoding the image: {0}")]
     Image(String),
+
+    #[error("invalid HTTP client configuration: {0}")]
+    Client(String),
+
+    #[error("tile is no longer visible")]
+    Cancelled,
+
+    #[error("tile not found")]
+    NotFound,
+
+    #[error(transparent)]
+    Fetcher(FetchError),
 }
 
-/// Download and decode the tile.
-async fn download_and_decode(client: &reqwest::Client, url: &str) -> Result<Tile, Error> {
-    let image = client
-        .get(url)
-        .header(USER_AGENT, "Walkers")
-        .send()
-        .await
-        .map_err(Error::Http)?;
+impl From<Error> for FetchError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Http(e) => FetchError::Http(e),
+            Error::Client(e) => FetchError::Client(e),
+            Error::Fetcher(e) => e,
+            e => FetchError::Other(Box::new(e)),
+        }
+    }
+}
+
+impl Error {
+    /// Whether trying again later has any chance of succeeding.
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Http(e) | Error::Fetcher(FetchError::Http(e)) => match e.status() {
+                Some(status) => {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                }
+                None => e.is_connect() || e.is_timeout() || e.is_body(),
+            },
+            Error::Image(_)
+            | Error::Client(_)
+            | Error::Cancelled
+            | Error::NotFound
+            | Error::Fetcher(_) => false,
+        }
+    }
+}
+
+/// Server's response to a (possibly conditional) tile request.
+enum Response<T> {
+    Modified(T, CachePolicy),
+    NotModified(CachePolicy),
+}
+
+/// Download the tile's image, without decoding it. If `stale` policy is given, the request is
+/// conditional, and the server might respond that the tile did not change.
+async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    stale: Option<&CachePolicy>,
+) -> Result<Response<Vec<u8>>, Error> {
+    let mut request = client.get(url);
+    if let Some(stale) = stale {
+        request = stale.conditional(request);
+    }
+
+    let image = request.send().await.map_err(Error::Http)?;
 
     log::debug!("Downloaded {:?}.", image.status());
 
-    let image = image
-        .error_for_status()
-        .map_err(Error::Http)?
+    if let Some(stale) = stale {
+        if image.status() == reqwest::StatusCode::NOT_MODIFIED {
+            let policy = stale
+                .clone()
+                .refreshed(image.headers(), now())
+                .fresh_for_at_least(MIN_FRESHNESS, now());
+            return Ok(Response::NotModified(policy));
+        }
+    }
+
+    // Servers do not have tiles e.g. outside of the area they cover, which is not an error.
+    if image.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(Error::NotFound);
+    }
+
+    let image = image.error_for_status().map_err(Error::Http)?;
+    let policy =
+        CachePolicy::from_headers(image.headers(), now()).fresh_for_at_least(MIN_FRESHNESS, now());
+
+    image
         .bytes()
         .await
-        .map_err(Error::Http)?;
+        .map(|bytes| Response::Modified(bytes.to_vec(), policy))
+        .map_err(Error::Http)
+}
+
+/// Adapts a [`TileSource`] to the [`TileFetcher`] interface, so it can be combined with other
+/// fetchers, e.g. to fall back to the network when a tile is not available offline. It is the
+/// same implementation [`crate::Tiles::with_options`] uses, so failures are retried and
+/// `404 Not Found` tiles are missing in both cases. However, as any other fetcher, it provides
+/// tiles of a single scale only (no HiDPI ones), and they never expire, so they are not
+/// revalidated.
+pub struct HttpFetcher {
+    source: Box<dyn TileSource + Send>,
+
+    /// If the client can not be built, each download fails with the reason.
+    client: Result<reqwest::Client, String>,
 
-    Tile::from_image_bytes(&image).map_err(Error::Image)
+    disk_cache: Option<DiskCache>,
+
+    /// Each scale of the tiles has its own directory, opened when first needed.
+    stores: Mutex<HashMap<u32, Arc<Mutex<LazyStore>>>>,
 }
 
-async fn download_continuously_impl<S>(
-    source: S,
-    mut request_rx: futures::channel::mpsc::Receiver<TileId>,
-    mut tile_tx: futures::channel::mpsc::Sender<(TileId, Tile)>,
+impl HttpFetcher {
+    pub fn new(
+        source: impl TileSource + Send + 'static,
+        http: &HttpOptions,
+    ) -> Result<Self, FetchError> {
+        let client = http.client()?;
+        Ok(Self::with_client(source, Ok(client)))
+    }
+
+    /// Keep the downloaded tiles in the disk cache, and look them up there before downloading.
+    pub fn with_disk_cache(mut self, disk_cache: DiskCache) -> Self {
+        self.disk_cache = Some(disk_cache);
+        self
+    }
+
+    /// Fetcher configured by [`TilesOptions`]. Unlike [`HttpFetcher::new`], invalid HTTP options
+    /// do not prevent creating it, but fail each download instead.
+    pub(crate) fn with_options(
+        source: impl TileSource + Send + 'static,
+        options: &TilesOptions,
+    ) -> Self {
+        let client = options.http.client().map_err(|e| {
+            log::error!("Could not create the HTTP client: {}", e);
+            e.to_string()
+        });
+
+        Self {
+            disk_cache: options.disk_cache.clone(),
+            ..Self::with_client(source, client)
+        }
+    }
+
+    fn with_client(
+        source: impl TileSource + Send + 'static,
+        client: Result<reqwest::Client, String>,
+    ) -> Self {
+        Self {
+            source: Box::new(source),
+            client,
+            disk_cache: None,
+            stores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Highest scale factor of the source's tiles, see [`TileSource::max_scale`].
+    pub(crate) fn max_scale(&self) -> u32 {
+        self.source.max_scale().max(1)
+    }
+
+    /// Prepare the download of the requested tile. It does not borrow the fetcher, so it can be
+    /// used in the IO task's futures.
+    fn download(&self, request: &Request) -> Download {
+        Download {
+            client: self.client.clone(),
+            store: self.store(request.scale),
+            url: self.source.scaled_tile_url(request.tile_id, request.scale),
+            tile_id: request.tile_id,
+            stale: request.stale.clone(),
+        }
+    }
+
+    fn store(&self, scale: u32) -> Arc<Mutex<LazyStore>> {
+        let Ok(mut stores) = self.stores.lock() else {
+            return Arc::new(Mutex::new(LazyStore::Unavailable));
+        };
+
+        stores
+            .entry(scale)
+            .or_insert_with(|| {
+                let disk_cache = self
+                    .disk_cache
+                    .as_ref()
+                    .map(|disk_cache| disk_cache.for_scale(scale));
+                Arc::new(Mutex::new(LazyStore::new(disk_cache)))
+            })
+            .clone()
+    }
+}
+
+impl TileFetcher for HttpFetcher {
+    fn fetch(&self, tile_id: TileId) -> TileFuture {
+        let download = self.download(&Request {
+            tile_id,
+            stale: None,
+            scale: 1,
+        });
+
+        Box::pin(async move {
+            match download.run(|image| Ok(image.to_vec())).await {
+                Ok(Response::Modified(image, _)) => Ok(Some(TileData::Bytes(image))),
+                Ok(Response::NotModified(_)) => {
+                    Err(FetchError::Other("unexpected 304 Not Modified".into()))
+                }
+                Err(Error::NotFound) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        self.source.attributions()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.source.tile_size()
+    }
+
+    fn grid(&self) -> TileGrid {
+        self.source.grid()
+    }
+}
+
+/// Single tile to be downloaded by the [`HttpFetcher`].
+struct Download {
+    client: Result<reqwest::Client, String>,
+    store: Arc<Mutex<LazyStore>>,
+    url: String,
+    tile_id: TileId,
+
+    /// Policy of a stale tile, which is already in the cache, but needs to be revalidated.
+    stale: Option<CachePolicy>,
+}
+
+impl Download {
+    /// Get the tile from the disk cache, or download it if it is not there. Tiles from the disk
+    /// cache are returned even if they are stale, [`crate::Tiles`] will ask for revalidation.
+    /// Tiles are decoded before being stored, so that broken images do not get there.
+    async fn run<T>(
+        &self,
+        decode: impl Fn(&[u8]) -> Result<T, String>,
+    ) -> Result<Response<T>, Error> {
+        if self.stale.is_none() {
+            // Tiles which can not be decoded are ignored, so they will be downloaded again.
+            if let Some((bytes, policy)) = load_from_disk(self.store.clone(), self.tile_id).await {
+                match decode(&bytes) {
+                    Ok(tile) => {
+                        log::debug!("Got {:?} from the disk cache.", self.tile_id);
+                        return Ok(Response::Modified(tile, policy));
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "Could not decode {:?} from the disk cache: {}",
+                            self.tile_id,
+                            e
+                        )
+                    }
+                }
+            }
+        }
+
+        // Invalid HTTP options should not prevent using the tiles which are already cached.
+        let client = self.client.as_ref().map_err(|e| Error::Client(e.clone()))?;
+
+        log::debug!("Getting {:?} from {}.", self.tile_id, self.url);
+
+        match download(client, &self.url, self.stale.as_ref()).await? {
+            Response::Modified(image, policy) => {
+                let tile = decode(&image).map_err(Error::Image)?;
+                save_to_disk(self.store.clone(), self.tile_id, image, policy.clone()).await;
+                Ok(Response::Modified(tile, policy))
+            }
+            Response::NotModified(policy) => {
+                update_policy_on_disk(self.store.clone(), self.tile_id, policy.clone()).await;
+                Ok(Response::NotModified(policy))
+            }
+        }
+    }
+}
+
+/// Get the tile from the disk cache. Like all disk cache operations, it runs on a blocking
+/// thread, as reading files (or indexing the whole cache) would stall other downloads.
+async fn load_from_disk(
+    store: Arc<Mutex<LazyStore>>,
+    tile_id: TileId,
+) -> Option<(Vec<u8>, CachePolicy)> {
+    blocking(move || store.lock().ok()?.open()?.get(tile_id))
+        .await
+        .flatten()
+}
+
+/// Tiles currently visible on the screen, shared between [`crate::Tiles`] and the IO task, so
+/// that tiles which are no longer needed are not downloaded.
+#[derive(Clone, Default)]
+pub(crate) struct Viewport(Arc<Mutex<Option<HashSet<TileId>>>>);
+
+impl Viewport {
+    pub fn set(&self, tiles: impl IntoIterator<Item = TileId>) {
+        if let Ok(mut visible) = self.0.lock() {
+            *visible = Some(tiles.into_iter().collect());
+        }
+    }
+
+    /// Whether the tile is visible. If the viewport was never set, all tiles are considered
+    /// visible.
+    fn contains(&self, tile_id: TileId) -> bool {
+        match self.0.lock() {
+            Ok(visible) => match visible.as_ref() {
+                Some(visible) => visible.contains(&tile_id),
+                None => true,
+            },
+            Err(_) => true,
+        }
+    }
+}
+
+/// Where the IO task gets the tiles from. Tiles downloaded over HTTP can be revalidated and have
+/// multiple scales, which other fetchers do not support.
+pub(crate) enum Source {
+    Http(HttpFetcher),
+
+    Fetcher(Box<dyn TileFetcher + Send>),
+}
+
+impl Source {
+    pub fn grid(&self) -> TileGrid {
+        match self {
+            Source::Http(http) => http.grid(),
+            Source::Fetcher(fetcher) => fetcher.grid(),
+        }
+    }
+
+    /// Fetchers provide tiles of a single scale only.
+    pub fn max_scale(&self) -> u32 {
+        match self {
+            Source::Http(http) => http.max_scale(),
+            Source::Fetcher(_) => 1,
+        }
+    }
+}
+
+/// Wait for the [`TileFetcher`]'s future. There is none if the tile is no longer visible.
+async fn fetch_with(
+    format: &Format,
+    tile_id: TileId,
+    future: Option<TileFuture>,
+) -> Result<Outcome, Error> {
+    let Some(future) = future else {
+        log::debug!("{:?} is no longer visible, not fetching it.", tile_id);
+        return Err(Error::Cancelled);
+    };
+
+    let tile = match future
+        .await
+        .map_err(Error::Fetcher)?
+        .ok_or(Error::NotFound)?
+    {
+        TileData::Bytes(bytes) => Tile::decode(&bytes, tile_id, format).map_err(Error::Image)?,
+        TileData::Image(image) => Tile::from_color_image(image),
+    };
+
+    // Fetchers have no notion of expiration.
+    Ok(Outcome::Loaded(tile, CachePolicy::default()))
+}
+
+/// Request for a tile, sent by [`crate::Tiles`].
+pub(crate) struct Request {
+    pub tile_id: TileId,
+
+    /// Policy of a stale tile, which is already in the cache, but needs to be revalidated.
+    pub stale: Option<CachePolicy>,
+
+    /// Scale factor of the tile, see [`TileSource::max_scale`].
+    pub scale: u32,
+}
+
+/// Result of the request, sent back to [`crate::Tiles`].
+pub(crate) enum Outcome {
+    Loaded(Tile, CachePolicy),
+
+    /// Stale tile did not change, and it is fresh again according to the new policy.
+    Revalidated(CachePolicy),
+
+    Failed(String),
+
+    /// Source does not have such tile, which is not an error, e.g. for sparse offline data.
+    Missing,
+
+    /// Tile went out of the viewport before it got downloaded.
+    Cancelled,
+}
+
+async fn save_to_disk(
+    store: Arc<Mutex<LazyStore>>,
+    tile_id: TileId,
+    image: Vec<u8>,
+    policy: CachePolicy,
+) {
+    if policy.no_store {
+        return;
+    }
+
+    blocking(move || {
+        if let Some(store) = store.lock().ok().as_mut().and_then(|store| store.open()) {
+            if let Err(e) = store.put(tile_id, &image, &policy) {
+                log::warn!("Could not store {:?} in the disk cache: {}", tile_id, e);
+            }
+        }
+    })
+    .await;
+}
+
+async fn update_policy_on_disk(store: Arc<Mutex<LazyStore>>, tile_id: TileId, policy: CachePolicy) {
+    blocking(move || {
+        if let Some(store) = store.lock().ok().as_mut().and_then(|store| store.open()) {
+            if let Err(e) = store.write_policy(tile_id, &policy) {
+                log::warn!("Could not update {:?} in the disk cache: {}", tile_id, e);
+            }
+        }
+    })
+    .await;
+}
+
+/// Download the tile over HTTP, unless it is no longer visible.
+async fn download_with(
+    download: Download,
+    viewport: &Viewport,
+    format: &Format,
+) -> Result<Outcome, Error> {
+    let tile_id = download.tile_id;
+
+    // User might have moved the map while this request was waiting in the queue, or for
+    // a retry.
+    if !viewport.contains(tile_id) {
+        log::debug!("{:?} is no longer visible, not downloading it.", tile_id);
+        return Err(Error::Cancelled);
+    }
+
+    match download
+        .run(|image| Tile::decode(image, tile_id, format))
+        .await?
+    {
+        Response::Modified(tile, policy) => Ok(Outcome::Loaded(tile, policy)),
+        Response::NotModified(policy) => Ok(Outcome::Revalidated(policy)),
+    }
+}
+
+async fn download_continuously_impl(
+    source: Source,
+    options: TilesOptions,
+    viewport: Viewport,
+    request_rx: futures::channel::mpsc::Receiver<Request>,
+    mut tile_tx: futures::channel::mpsc::Sender<(TileId, Outcome)>,
     egui_ctx: Context,
-) -> Result<(), ()>
-where
-    S: TileSource + Send + 'static,
-{
-    // Keep outside the loop to reuse it as much as possible.
-    let client = reqwest::Client::new();
-
-    loop {
-        let request = request_rx.next().await.ok_or(())?;
-        let url = source.tile_url(request);
-
-        log::debug!("Getting {:?} from {}.", request, url);
-
-        match download_and_decode(&client, &url).await {
-            Ok(tile) => {
-                tile_tx.try_send((request, tile)).map_err(|_| ())?;
-                egui_ctx.request_repaint();
+) -> Result<(), ()> {
+    let format = options.format(source.grid().tile_size());
+    let retry = options.retry;
+
+    // Requests which failed transiently wait for their backoff outside of the downloads below,
+    // so they do not take the slots of other tiles. Then they are queued again, with the number
+    // of the next attempt.
+    let (retry_tx, retry_rx) = futures::channel::mpsc::unbounded();
+    let retries = retry_rx
+        .map(|(request, attempt, backoff)| async move {
+            sleep(backoff).await;
+            Some((request, attempt))
+        })
+        .buffer_unordered(usize::MAX);
+
+    // Retries are dropped once the request channel gets closed.
+    let requests = futures::stream::select(
+        request_rx
+            .map(|request| Some((request, 0)))
+            .chain(futures::stream::once(futures::future::ready(None))),
+        retries,
+    )
+    .take_while(|request| futures::future::ready(request.is_some()))
+    .filter_map(futures::future::ready);
+
+    // Up to `parallelism` tiles are downloaded at the same time, in no particular order.
+    let mut downloads = requests
+        .map(move |(request, attempt): (Request, u32)| {
+            let tile_id = request.tile_id;
+            let viewport = viewport.clone();
+            let format = format.clone();
+            let load = match &source {
+                Source::Http(http) => {
+                    let download = http.download(&request);
+                    Either::Left(async move { download_with(download, &viewport, &format).await })
+                }
+                Source::Fetcher(fetcher) => {
+                    // Fetchers might do their work as soon as they are called, e.g. read a file,
+                    // so they are not called at all for tiles which are no longer visible. This
+                    // closure runs once there is a free slot, right before the tile is fetched.
+                    let future = viewport.contains(tile_id).then(|| fetcher.fetch(tile_id));
+                    Either::Right(async move { fetch_with(&format, tile_id, future).await })
+                }
+            };
+            async move { (request, attempt, load.await) }
+        })
+        .buffer_unordered(options.parallelism.max(1));
+
+    while let Some((request, attempt, result)) = downloads.next().await {
+        let tile_id = request.tile_id;
+
+        // Failures are sent too, so they can be told apart from tiles still being downloaded.
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            Err(Error::Cancelled) => Outcome::Cancelled,
+            Err(Error::NotFound) => Outcome::Missing,
+            Err(e) if e.is_transient() && attempt < retry.max_retries => {
+                let backoff = retry.backoff(attempt);
+                log::info!(
+                    "Could not download {:?}: {}, retrying in {:?}.",
+                    tile_id,
+                    e,
+                    backoff
+                );
+                retry_tx
+                    .unbounded_send((request, attempt + 1, backoff))
+                    .map_err(|_| ())?;
+                continue;
             }
             Err(e) => {
-                log::warn!("Could not download '{}': {}", &url, e);
+                log::warn!("Could not download {:?}: {}", tile_id, e);
+                Outcome::Failed(e.to_string())
             }
-        }
+        };
+        tile_tx.send((tile_id, outcome)).await.map_err(|_| ())?;
+        egui_ctx.request_repaint();
     }
+
+    // Request channel got closed.
+    Err(())
 }
 
 /// Continuously download tiles requested via request channel.
-pub async fn download_continuously<S>(
-    source: S,
-    request_rx: futures::channel::mpsc::Receiver<TileId>,
-    tile_tx: futures::channel::mpsc::Sender<(TileId, Tile)>,
+pub(crate) async fn download_continuously(
+    source: Source,
+    options: TilesOptions,
+    viewport: Viewport,
+    request_rx: futures::channel::mpsc::Receiver<Request>,
+    tile_tx: futures::channel::mpsc::Sender<(TileId, Outcome)>,
     egui_ctx: Context,
-) where
-    S: TileSource + Send + 'static,
-{
-    if download_continuously_impl(source, request_rx, tile_tx, egui_ctx)
+) {
+    if download_continuously_impl(source, options, viewport, request_rx, tile_tx, egui_ctx)
         .await
         .is_err()
     {
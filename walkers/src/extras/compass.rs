@@ -0,0 +1,75 @@
+use egui::{emath::Rot2, Align2, Color32, Painter, Rect, Response, Sense, Shape, Stroke, Ui, Vec2};
+
+use crate::{MapMemory, Plugin, Projector};
+
+/// [`Plugin`] which shows the map's bearing in one of its corners. Clicking it turns the map
+/// back to the north.
+pub struct Compass {
+    corner: Align2,
+    size: f32,
+}
+
+impl Compass {
+    /// Compass in the given corner of the map.
+    pub fn new(corner: Align2) -> Self {
+        Self { corner, size: 40. }
+    }
+
+    /// Diameter of the compass, in points.
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    fn rect(&self, map_rect: Rect) -> Rect {
+        self.corner
+            .align_size_within_rect(Vec2::splat(self.size), map_rect.shrink(8.))
+    }
+}
+
+impl Default for Compass {
+    fn default() -> Self {
+        Self::new(Align2::RIGHT_TOP)
+    }
+}
+
+impl Plugin for Compass {
+    fn draw(&self, painter: Painter, projector: &Projector) {
+        let rect = self.rect(painter.clip_rect());
+        let center = rect.center();
+        let radius = rect.width() / 2.;
+        let visuals = painter.ctx().style().visuals.clone();
+
+        painter.circle(
+            center,
+            radius,
+            visuals.extreme_bg_color.gamma_multiply(0.8),
+            visuals.widgets.noninteractive.bg_stroke,
+        );
+
+        // Needle turns along with the map, so it keeps pointing to the north.
+        let north =
+            Rot2::from_angle(-projector.bearing().to_radians()) * Vec2::new(0., -0.7 * radius);
+        let side = north.rot90() * 0.25;
+
+        painter.add(Shape::convex_polygon(
+            vec![center + north, center + side, center - side],
+            Color32::from_rgb(220, 50, 50),
+            Stroke::NONE,
+        ));
+        painter.add(Shape::convex_polygon(
+            vec![center - north, center - side, center + side],
+            visuals.weak_text_color(),
+            Stroke::NONE,
+        ));
+    }
+
+    fn interact(&self, ui: &mut Ui, response: &Response, memory: &mut MapMemory) {
+        let rect = self.rect(response.rect);
+        let compass = ui.interact(rect, response.id.with("compass"), Sense::click());
+
+        if compass.on_hover_text("Turn to the north").clicked() {
+            memory.bearing = 0.;
+        }
+    }
+}
@@ -3,3 +3,5 @@ mod places;
 pub use places::{Place, Places, Style};
 mod images;
 pub use images::{Image, Images, Texture};
+mod compass;
+pub use compass::Compass;
@@ -0,0 +1,881 @@
+//! Tile grids, describing how the tiles of each zoom level are laid out in a coordinate reference
+//! system (CRS), and transformations between geographical positions and these systems. Most of
+//! the tile servers use the Web Mercator (EPSG:3857), which is therefore the default.
+
+use std::{f64::consts::PI, fmt, sync::Arc};
+
+use egui::Vec2;
+use geo_types::{coord, Coord, Rect};
+
+use crate::mercator::{Position, TileId, TILE_SIZE, WEB_MERCATOR_EXTENT};
+
+/// Coordinate reference system, into which geographical positions are projected.
+pub trait Crs: Send + Sync {
+    /// Identifier, e.g. `EPSG:3857`. Tiles can be drawn only on a map of the same CRS.
+    fn code(&self) -> &str;
+
+    /// Projected coordinates (typically easting and northing) of the position.
+    fn project(&self, position: Position) -> Coord;
+
+    /// Geographical position of the projected coordinates.
+    fn unproject(&self, coord: Coord) -> Position;
+
+    /// Length of the CRS's unit, in meters.
+    fn meters_per_unit(&self) -> f64 {
+        1.
+    }
+
+    /// Whether the CRS defines northing (or latitude) as its first axis, as EPSG:4326 and
+    /// EPSG:2180 do. OGC services, such as WMTS, write the coordinates in that order.
+    fn northing_first(&self) -> bool {
+        false
+    }
+}
+
+/// Web Mercator (EPSG:3857), in meters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WebMercator;
+
+impl Crs for WebMercator {
+    fn code(&self) -> &str {
+        "EPSG:3857"
+    }
+
+    fn project(&self, position: Position) -> Coord {
+        let radius = WEB_MERCATOR_EXTENT / PI;
+        coord! {
+            x: position.x().to_radians() * radius,
+            y: position.y().to_radians().tan().asinh() * radius,
+        }
+    }
+
+    fn unproject(&self, coord: Coord) -> Position {
+        let radius = WEB_MERCATOR_EXTENT / PI;
+        Position::new(
+            (coord.x / radius).to_degrees(),
+            (coord.y / radius).sinh().atan().to_degrees(),
+        )
+    }
+}
+
+/// Longitude and latitude (EPSG:4326) used directly as the coordinates, i.e. the equirectangular
+/// projection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Wgs84;
+
+impl Crs for Wgs84 {
+    fn code(&self) -> &str {
+        "EPSG:4326"
+    }
+
+    fn project(&self, position: Position) -> Coord {
+        position.into()
+    }
+
+    fn unproject(&self, coord: Coord) -> Position {
+        coord.into()
+    }
+
+    /// Length of a degree of the equator.
+    fn meters_per_unit(&self) -> f64 {
+        WEB_MERCATOR_EXTENT / 180.
+    }
+
+    fn northing_first(&self) -> bool {
+        true
+    }
+}
+
+/// Transverse Mercator on the GRS 80 ellipsoid, used by many national grids.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransverseMercator {
+    /// Identifier, e.g. `EPSG:2180`.
+    pub code: String,
+
+    /// Longitude of the central meridian, in degrees.
+    pub central_meridian: f64,
+
+    /// Scale factor at the central meridian.
+    pub scale_factor: f64,
+
+    pub false_easting: f64,
+    pub false_northing: f64,
+
+    /// See [`Crs::northing_first`].
+    pub northing_first: bool,
+}
+
+impl TransverseMercator {
+    /// Polish "PUWG 1992" (EPSG:2180), used e.g. by the national Geoportal.
+    pub fn puwg_1992() -> Self {
+        Self {
+            code: "EPSG:2180".to_owned(),
+            central_meridian: 19.,
+            scale_factor: 0.9993,
+            false_easting: 500_000.,
+            false_northing: -5_300_000.,
+            northing_first: true,
+        }
+    }
+}
+
+/// Coefficients of the Krüger series for the GRS 80 ellipsoid, which are accurate to a millimeter
+/// within thousands of kilometers from the central meridian.
+struct Kruger {
+    eccentricity: f64,
+
+    /// Radius of the rectifying sphere.
+    radius: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+    delta: [f64; 3],
+}
+
+impl Kruger {
+    fn grs80() -> Self {
+        let semi_major_axis = 6_378_137.;
+        let flattening: f64 = 1. / 298.257_222_101;
+        let n = flattening / (2. - flattening);
+        let (n2, n3) = (n * n, n * n * n);
+
+        Self {
+            eccentricity: (flattening * (2. - flattening)).sqrt(),
+            radius: semi_major_axis / (1. + n) * (1. + n2 / 4. + n2 * n2 / 64.),
+            alpha: [
+                n / 2. - 2. * n2 / 3. + 5. * n3 / 16.,
+                13. * n2 / 48. - 3. * n3 / 5.,
+                61. * n3 / 240.,
+            ],
+            beta: [
+                n / 2. - 2. * n2 / 3. + 37. * n3 / 96.,
+                n2 / 48. + n3 / 15.,
+                17. * n3 / 480.,
+            ],
+            delta: [
+                2. * n - 2. * n2 / 3. - 2. * n3,
+                7. * n2 / 3. - 8. * n3 / 5.,
+                56. * n3 / 15.,
+            ],
+        }
+    }
+}
+
+impl Crs for TransverseMercator {
+    fn code(&self) -> &str {
+        &self.code
+    }
+
+    fn project(&self, position: Position) -> Coord {
+        let kruger = Kruger::grs80();
+        let e = kruger.eccentricity;
+        let latitude = position.y().to_radians();
+        let longitude = (position.x() - self.central_meridian).to_radians();
+
+        // Conformal latitude, and the coordinates on the sphere.
+        let t = (latitude.sin().atanh() - e * (e * latitude.sin()).atanh()).sinh();
+        let xi = t.atan2(longitude.cos());
+        let eta = (longitude.sin() / (1. + t * t).sqrt()).atanh();
+
+        let (mut x, mut y) = (eta, xi);
+        for (j, alpha) in kruger.alpha.iter().enumerate() {
+            let j = 2. * (j + 1) as f64;
+            x += alpha * (j * xi).cos() * (j * eta).sinh();
+            y += alpha * (j * xi).sin() * (j * eta).cosh();
+        }
+
+        let scale = self.scale_factor * kruger.radius;
+        coord! {
+            x: self.false_easting + scale * x,
+            y: self.false_northing + scale * y,
+        }
+    }
+
+    fn unproject(&self, coord: Coord) -> Position {
+        let kruger = Kruger::grs80();
+        let scale = self.scale_factor * kruger.radius;
+        let xi = (coord.y - self.false_northing) / scale;
+        let eta = (coord.x - self.false_easting) / scale;
+
+        let (mut xi_prime, mut eta_prime) = (xi, eta);
+        for (j, beta) in kruger.beta.iter().enumerate() {
+            let j = 2. * (j + 1) as f64;
+            xi_prime -= beta * (j * xi).sin() * (j * eta).cosh();
+            eta_prime -= beta * (j * xi).cos() * (j * eta).sinh();
+        }
+
+        let chi = (xi_prime.sin() / eta_prime.cosh()).asin();
+        let mut latitude = chi;
+        for (j, delta) in kruger.delta.iter().enumerate() {
+            latitude += delta * (2. * (j + 1) as f64 * chi).sin();
+        }
+        let longitude = eta_prime.sinh().atan2(xi_prime.cos());
+
+        Position::new(
+            self.central_meridian + longitude.to_degrees(),
+            latitude.to_degrees(),
+        )
+    }
+
+    fn northing_first(&self) -> bool {
+        self.northing_first
+    }
+}
+
+/// Resolution and size of the tile matrix of a single zoom level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Level {
+    /// Size of a pixel, in the units of the CRS.
+    pub resolution: f64,
+
+    /// Number of tiles in each row.
+    pub matrix_width: u32,
+
+    /// Number of tiles in each column.
+    pub matrix_height: u32,
+}
+
+/// Index of the most detailed level of the built-in grids.
+const MAX_LEVEL: u8 = 24;
+
+/// Tiles of consecutive zoom levels, laid out in the CRS from the top-left corner (origin) of the
+/// grid. [`TileId::zoom`] is the index of the level, while [`TileId::x`] and [`TileId::y`] are
+/// counted to the east and south, respectively.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use walkers::{Level, TileGrid, TransverseMercator};
+/// // Three levels of 512 pixel tiles, covering Poland.
+/// let grid = TileGrid::new(
+///     Arc::new(TransverseMercator::puwg_1992()),
+///     geo_types::coord! { x: 100_000., y: 850_000. },
+///     512,
+///     [1000., 500., 200.]
+///         .map(|resolution| Level {
+///             resolution,
+///             matrix_width: (1_000_000. / 512. / resolution).ceil() as u32,
+///             matrix_height: (1_000_000. / 512. / resolution).ceil() as u32,
+///         })
+///         .to_vec(),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct TileGrid {
+    crs: Arc<dyn Crs>,
+    origin: Coord,
+    tile_size: u32,
+    levels: Vec<Level>,
+}
+
+impl TileGrid {
+    /// Grid of tiles of given size (in pixels), with the levels ordered from the least detailed.
+    pub fn new(crs: Arc<dyn Crs>, origin: Coord, tile_size: u32, levels: Vec<Level>) -> Self {
+        Self {
+            crs,
+            origin,
+            tile_size,
+            levels,
+        }
+    }
+
+    /// Square grid of the Web Mercator, with a single tile at level 0, as used by OpenStreetMap
+    /// and most of the other tile servers.
+    pub fn web_mercator(tile_size: u32) -> Self {
+        let levels = (0..=MAX_LEVEL)
+            .map(|level| {
+                let tiles = 2u32.pow(level as u32);
+                Level {
+                    resolution: 2. * WEB_MERCATOR_EXTENT / (tile_size as f64 * tiles as f64),
+                    matrix_width: tiles,
+                    matrix_height: tiles,
+                }
+            })
+            .collect();
+
+        Self::new(
+            Arc::new(WebMercator),
+            coord! { x: -WEB_MERCATOR_EXTENT, y: WEB_MERCATOR_EXTENT },
+            tile_size,
+            levels,
+        )
+    }
+
+    /// Grid of the EPSG:4326, with two tiles (western and eastern hemisphere) at level 0.
+    pub fn wgs84(tile_size: u32) -> Self {
+        let levels = (0..=MAX_LEVEL)
+            .map(|level| {
+                let tiles = 2u32.pow(level as u32);
+                Level {
+                    resolution: 180. / (tile_size as f64 * tiles as f64),
+                    matrix_width: 2 * tiles,
+                    matrix_height: tiles,
+                }
+            })
+            .collect();
+
+        Self::new(
+            Arc::new(Wgs84),
+            coord! { x: -180., y: 90. },
+            tile_size,
+            levels,
+        )
+    }
+
+    pub fn crs(&self) -> &dyn Crs {
+        self.crs.as_ref()
+    }
+
+    /// Top-left corner of the grid, in the units of the CRS.
+    pub fn origin(&self) -> Coord {
+        self.origin
+    }
+
+    /// Size of the tiles, in pixels.
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+
+    /// Area covered by the tile, in the units of the CRS. `None` if there is no such level.
+    pub fn tile_bounding_box(&self, tile_id: TileId) -> Option<Rect> {
+        let span = self.tile_span(tile_id.zoom)?;
+        let west = self.origin.x + tile_id.x as f64 * span;
+        let north = self.origin.y - tile_id.y as f64 * span;
+
+        Some(Rect::new(
+            coord! { x: west, y: north - span },
+            coord! { x: west + span, y: north },
+        ))
+    }
+
+    /// Whether tiles of both grids can be drawn on the same map.
+    pub(crate) fn same_crs(&self, other: &TileGrid) -> bool {
+        self.crs.code() == other.crs.code()
+    }
+
+    /// Resolution of the map at given, possibly fractional, zoom. It is interpolated between the
+    /// levels, and extrapolated beyond the last one.
+    pub(crate) fn resolution(&self, zoom: f64) -> f64 {
+        let zoom = zoom.max(0.);
+        let index = (zoom as usize).min(self.levels.len().saturating_sub(2));
+        let resolution = self.levels.get(index).map_or(1., |level| level.resolution);
+        let ratio = self
+            .levels
+            .get(index + 1)
+            .map_or(0.5, |next| next.resolution / resolution);
+
+        resolution * ratio.powf(zoom - index as f64)
+    }
+
+    /// Level which is the closest match for the given resolution, so its tiles are scaled as
+    /// little as possible. In case of a tie, the more detailed one.
+    pub(crate) fn level(&self, resolution: f64) -> Option<u8> {
+        let distance = |level: &Level| (level.resolution / resolution).ln().abs();
+
+        self.levels
+            .iter()
+            .enumerate()
+            .rev()
+            .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+            .map(|(index, _)| index as u8)
+    }
+
+    /// Tiles of given level which cover the viewport of given size (in pixels), centered at given
+    /// point and drawn at given resolution. The tiles closest to the center come first, so they
+    /// can be downloaded first.
+    pub(crate) fn visible_tiles(
+        &self,
+        center: Coord,
+        viewport: Vec2,
+        resolution: f64,
+        level: u8,
+    ) -> Vec<TileId> {
+        let half = coord! {
+            x: viewport.x as f64 * resolution / 2.,
+            y: viewport.y as f64 * resolution / 2.,
+        };
+        let mut tiles = self.tiles_within(Rect::new(center - half, center + half), level);
+
+        if let Some(span) = self.tile_span(level) {
+            let center = coord! {
+                x: (center.x - self.origin.x) / span,
+                y: (self.origin.y - center.y) / span,
+            };
+            let distance = |tile_id: &TileId| {
+                let x = tile_id.x as f64 + 0.5 - center.x;
+                let y = tile_id.y as f64 + 0.5 - center.y;
+                x * x + y * y
+            };
+
+            tiles.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        }
+
+        tiles
+    }
+
+    /// Tile of the previous level, which covers the center of this one.
+    pub(crate) fn parent(&self, tile_id: TileId) -> Option<TileId> {
+        let center = self.tile_bounding_box(tile_id)?.center();
+        let level = tile_id.zoom.checked_sub(1)?;
+        let matrix = self.levels.get(level as usize)?;
+        let span = self.tile_span(level)?;
+
+        let x = ((center.x - self.origin.x) / span).floor();
+        let y = ((self.origin.y - center.y) / span).floor();
+        let within = (0. ..matrix.matrix_width as f64).contains(&x)
+            && (0. ..matrix.matrix_height as f64).contains(&y);
+
+        within.then_some(TileId {
+            x: x as u32,
+            y: y as u32,
+            zoom: level,
+        })
+    }
+
+    /// Tiles of the next level, which overlap this one.
+    pub(crate) fn children(&self, tile_id: TileId) -> Vec<TileId> {
+        match (self.tile_bounding_box(tile_id), tile_id.zoom.checked_add(1)) {
+            (Some(bounding_box), Some(level)) => self.tiles_within(bounding_box, level),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Size of the tiles of given level, in the units of the CRS.
+    fn tile_span(&self, level: u8) -> Option<f64> {
+        let level = self.levels.get(level as usize)?;
+        Some(self.tile_size as f64 * level.resolution)
+    }
+
+    /// Tiles of given level which overlap the area. Tiles outside of the matrix do not exist.
+    fn tiles_within(&self, area: Rect, level: u8) -> Vec<TileId> {
+        let (Some(matrix), Some(span)) = (self.levels.get(level as usize), self.tile_span(level))
+        else {
+            return Vec::new();
+        };
+
+        // Tolerance for the rounding errors, so that areas ending exactly at the tiles' edges do
+        // not reach the neighbours.
+        let epsilon = 1e-6;
+        let range = |min: f64, max: f64, size: u32| {
+            let min = (min + epsilon).floor().max(0.);
+            let max = ((max - epsilon).ceil() - 1.).min(size as f64 - 1.);
+            if max < min {
+                0..0
+            } else {
+                min as u32..max as u32 + 1
+            }
+        };
+
+        let columns = range(
+            (area.min().x - self.origin.x) / span,
+            (area.max().x - self.origin.x) / span,
+            matrix.matrix_width,
+        );
+        let rows = range(
+            (self.origin.y - area.max().y) / span,
+            (self.origin.y - area.min().y) / span,
+            matrix.matrix_height,
+        );
+
+        rows.flat_map(|y| columns.clone().map(move |x| TileId { x, y, zoom: level }))
+            .collect()
+    }
+
+    /// Offset (in pixels) from the `center` to the `position`, on the map drawn at given,
+    /// possibly fractional, zoom.
+    pub(crate) fn offset(&self, center: Position, position: Position, zoom: f64) -> Vec2 {
+        screen_offset(
+            self.crs.project(center),
+            self.crs.project(position),
+            self.resolution(zoom),
+        )
+    }
+
+    /// Position at given offset (in pixels) from the `center`, on the map drawn at given,
+    /// possibly fractional, zoom.
+    pub(crate) fn position_at(&self, center: Position, offset: Vec2, zoom: f64) -> Position {
+        let resolution = self.resolution(zoom);
+        let center = self.crs.project(center);
+
+        self.crs.unproject(coord! {
+            x: center.x + offset.x as f64 * resolution,
+            y: center.y - offset.y as f64 * resolution,
+        })
+    }
+}
+
+impl Default for TileGrid {
+    fn default() -> Self {
+        Self::web_mercator(TILE_SIZE)
+    }
+}
+
+impl fmt::Debug for TileGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TileGrid")
+            .field("crs", &self.crs.code())
+            .field("origin", &self.origin)
+            .field("tile_size", &self.tile_size)
+            .field("levels", &self.levels)
+            .finish()
+    }
+}
+
+/// Offset (in pixels, with the y axis pointing down) between two projected coordinates, drawn at
+/// given resolution.
+pub(crate) fn screen_offset(from: Coord, to: Coord, resolution: f64) -> Vec2 {
+    Vec2::new(
+        ((to.x - from.x) / resolution) as f32,
+        ((from.y - to.y) / resolution) as f32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mercator::{screen_to_position, PositionExt};
+
+    #[test]
+    fn web_mercator_grid_matches_the_world_bitmap() {
+        let grid = TileGrid::default();
+        let citadel = Position::new(21.00027, 52.26470);
+        let center = Position::new(17.03664, 51.09916);
+
+        for zoom in [3., 16., 16.3] {
+            let expected = citadel.project(zoom) - center.project(zoom);
+            let offset = grid.offset(center, citadel, zoom);
+            approx::assert_relative_eq!(expected.x, offset.x, max_relative = 0.001);
+            approx::assert_relative_eq!(expected.y, offset.y, max_relative = 0.001);
+
+            let position = grid.position_at(center, offset, zoom);
+            let expected = screen_to_position(center.project(zoom) + offset, zoom);
+            approx::assert_relative_eq!(expected.x(), position.x(), max_relative = 0.00001);
+            approx::assert_relative_eq!(expected.y(), position.y(), max_relative = 0.00001);
+        }
+
+        let tile_id = citadel.tile_id(16);
+        let bounding_box = grid.tile_bounding_box(tile_id).unwrap();
+        assert_eq!(tile_id.bounding_box().min(), bounding_box.min());
+        assert_eq!(tile_id.bounding_box().max(), bounding_box.max());
+    }
+
+    #[test]
+    fn resolution_between_and_beyond_the_levels() {
+        let grid = TileGrid::default();
+        approx::assert_relative_eq!(grid.levels()[5].resolution, grid.resolution(5.));
+        approx::assert_relative_eq!(
+            grid.levels()[5].resolution / 2f64.sqrt(),
+            grid.resolution(5.5)
+        );
+        approx::assert_relative_eq!(grid.levels()[24].resolution / 4., grid.resolution(26.));
+
+        // Levels do not need to be twice as detailed as the previous ones.
+        let grid = TileGrid::new(
+            Arc::new(WebMercator),
+            coord! { x: 0., y: 0. },
+            256,
+            [100., 40., 20.]
+                .map(|resolution| Level {
+                    resolution,
+                    matrix_width: 10,
+                    matrix_height: 10,
+                })
+                .to_vec(),
+        );
+        approx::assert_relative_eq!(40., grid.resolution(1.));
+        approx::assert_relative_eq!(100. * 0.4f64.sqrt(), grid.resolution(0.5));
+        approx::assert_relative_eq!(10., grid.resolution(3.));
+        assert_eq!(Some(1), grid.level(50.));
+        assert_eq!(Some(2), grid.level(1.));
+    }
+
+    #[test]
+    fn level_depends_on_the_tile_size() {
+        let map = TileGrid::default();
+        let level = |tile_size, zoom| TileGrid::web_mercator(tile_size).level(map.resolution(zoom));
+
+        assert_eq!(Some(5), level(256, 5.));
+        assert_eq!(Some(6), level(256, 5.6));
+
+        // 512 pixel tiles of level 4 are drawn at zoom 5, at their natural size.
+        assert_eq!(Some(4), level(512, 5.));
+        assert_eq!(Some(5), level(512, 5.6));
+        assert_eq!(Some(0), level(512, 0.3));
+        assert_eq!(Some(3), level(1024, 5.));
+    }
+
+    #[test]
+    fn big_tiles_cover_the_viewport() {
+        let map = TileGrid::default();
+        let grid = TileGrid::web_mercator(512);
+        let resolution = map.resolution(5.);
+        let level = grid.level(resolution).unwrap();
+        let tile_id = TileId {
+            x: 10,
+            y: 12,
+            zoom: level,
+        };
+
+        let bounding_box = grid.tile_bounding_box(tile_id).unwrap();
+        assert_eq!(
+            Vec2::splat(512.),
+            screen_offset(
+                coord! { x: bounding_box.min().x, y: bounding_box.max().y },
+                coord! { x: bounding_box.max().x, y: bounding_box.min().y },
+                resolution
+            )
+        );
+
+        let center = bounding_box.center();
+        assert_eq!(
+            vec![tile_id],
+            grid.visible_tiles(center, Vec2::splat(500.), resolution, level)
+        );
+        assert_eq!(
+            9,
+            grid.visible_tiles(center, Vec2::splat(600.), resolution, level)
+                .len()
+        );
+
+        // Same area is covered by four times as many 256 pixel tiles.
+        assert_eq!(
+            4,
+            map.visible_tiles(center, Vec2::splat(500.), resolution, 5)
+                .len()
+        );
+    }
+
+    #[test]
+    fn tiles_covering_the_viewport() {
+        let grid = TileGrid::default();
+        let resolution = grid.resolution(5.);
+        let tile_id = TileId {
+            x: 10,
+            y: 12,
+            zoom: 5,
+        };
+        let center = grid.tile_bounding_box(tile_id).unwrap().center();
+
+        // Viewport is a bit bigger than a tile, so it reaches all neighbours.
+        let tiles = grid.visible_tiles(center, Vec2::splat(300.), resolution, 5);
+        assert_eq!(9, tiles.len());
+        assert_eq!(tile_id, tiles[0]);
+
+        // Direct neighbours are closer than the diagonal ones.
+        let mut direct: Vec<_> = tiles[1..5].to_vec();
+        direct.sort_by_key(|tile_id| (tile_id.x, tile_id.y));
+        assert_eq!(
+            vec![
+                tile_id.west().unwrap(),
+                tile_id.north().unwrap(),
+                tile_id.south().unwrap(),
+                tile_id.east().unwrap(),
+            ],
+            direct
+        );
+
+        // Smaller viewport fits in a single tile.
+        assert_eq!(
+            vec![tile_id],
+            grid.visible_tiles(center, Vec2::splat(200.), resolution, 5)
+        );
+    }
+
+    #[test]
+    fn visible_tiles_are_scaled_with_fractional_zoom() {
+        let grid = TileGrid::default();
+        let tile_id = TileId {
+            x: 10,
+            y: 12,
+            zoom: 5,
+        };
+        let center = grid.tile_bounding_box(tile_id).unwrap().center();
+
+        // At zoom 6 each tile is twice as big, so the viewport fits within a single one.
+        assert_eq!(
+            vec![tile_id],
+            grid.visible_tiles(center, Vec2::splat(500.), grid.resolution(6.), 5)
+        );
+
+        // And at zoom 4 they are twice as small.
+        assert_eq!(
+            25,
+            grid.visible_tiles(center, Vec2::splat(500.), grid.resolution(4.), 5)
+                .len()
+        );
+    }
+
+    #[test]
+    fn visible_tiles_do_not_exceed_the_world() {
+        let grid = TileGrid::default();
+        let center = coord! { x: 0., y: 0. };
+
+        let tiles = grid.visible_tiles(center, Vec2::splat(1000.), grid.resolution(0.), 0);
+        assert_eq!(
+            vec![TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            }],
+            tiles
+        );
+
+        // North-west corner of the world.
+        let corner = grid.origin();
+        let tiles = grid.visible_tiles(corner, Vec2::splat(1000.), grid.resolution(3.), 3);
+        assert_eq!(4, tiles.len());
+        assert_eq!(
+            TileId {
+                x: 0,
+                y: 0,
+                zoom: 3
+            },
+            tiles[0]
+        );
+
+        // Beyond the world.
+        let outside = corner - coord! { x: 1e7, y: 0. };
+        assert!(grid
+            .visible_tiles(outside, Vec2::splat(1000.), grid.resolution(3.), 3)
+            .is_empty());
+    }
+
+    #[test]
+    fn parents_and_children_match_the_web_mercator_tiles() {
+        let grid = TileGrid::default();
+        let tile_id = TileId {
+            x: 5,
+            y: 2,
+            zoom: 3,
+        };
+
+        assert_eq!(tile_id.parent(), grid.parent(tile_id));
+        assert_eq!(tile_id.children().to_vec(), grid.children(tile_id));
+        assert_eq!(
+            None,
+            grid.parent(TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            })
+        );
+    }
+
+    #[test]
+    fn parents_and_children_of_uneven_levels() {
+        // Tiles of the next level are 30% of the previous ones, so they do not line up.
+        let grid = TileGrid::new(
+            Arc::new(TransverseMercator::puwg_1992()),
+            coord! { x: 0., y: 1000. },
+            100,
+            [1., 0.3]
+                .map(|resolution| Level {
+                    resolution,
+                    matrix_width: (10. / resolution) as u32,
+                    matrix_height: (10. / resolution) as u32,
+                })
+                .to_vec(),
+        );
+
+        let tile_id = TileId {
+            x: 1,
+            y: 0,
+            zoom: 0,
+        };
+        let children = grid.children(tile_id);
+        assert_eq!(16, children.len());
+        assert_eq!(
+            TileId {
+                x: 3,
+                y: 0,
+                zoom: 1
+            },
+            children[0]
+        );
+        assert_eq!(
+            TileId {
+                x: 6,
+                y: 3,
+                zoom: 1
+            },
+            children[15]
+        );
+
+        // Child overlapping two tiles belongs to the one covering its center.
+        assert_eq!(Some(tile_id), grid.parent(children[0]));
+        assert_eq!(
+            Some(TileId { x: 0, ..tile_id }),
+            grid.parent(TileId {
+                x: 2,
+                ..children[0]
+            })
+        );
+        assert_eq!(Some(TileId { y: 1, ..tile_id }), grid.parent(children[15]));
+    }
+
+    #[test]
+    fn wgs84_grid_has_two_tiles_at_level_0() {
+        let grid = TileGrid::wgs84(256);
+        let resolution = grid.resolution(0.);
+        assert_eq!(180. / 256., resolution);
+
+        let tiles = grid.visible_tiles(
+            coord! { x: 0., y: 0. },
+            Vec2::new(512., 256.),
+            resolution,
+            0,
+        );
+        assert_eq!(2, tiles.len());
+
+        let eastern = grid
+            .tile_bounding_box(TileId {
+                x: 1,
+                y: 0,
+                zoom: 0,
+            })
+            .unwrap();
+        assert_eq!(coord! { x: 0., y: -90. }, eastern.min());
+        assert_eq!(coord! { x: 180., y: 90. }, eastern.max());
+
+        let position = Position::new(17.03664, 51.09916);
+        assert_eq!(
+            coord! { x: 17.03664, y: 51.09916 },
+            grid.crs().project(position)
+        );
+        assert_eq!(
+            Vec2::new(256., -128.),
+            grid.offset(Position::new(0., 0.), Position::new(180., 90.), 0.)
+        );
+    }
+
+    #[test]
+    fn transverse_mercator() {
+        let crs = TransverseMercator::puwg_1992();
+
+        // Central meridian is not distorted, apart from the scale factor.
+        let origin = crs.project(Position::new(19., 0.));
+        approx::assert_abs_diff_eq!(500_000., origin.x, epsilon = 0.001);
+        approx::assert_abs_diff_eq!(-5_300_000., origin.y, epsilon = 0.001);
+
+        // Reference values from the Snyder's series (USGS Professional Paper 1395).
+        let on_meridian = crs.project(Position::new(19., 52.));
+        approx::assert_abs_diff_eq!(500_000., on_meridian.x, epsilon = 0.01);
+        approx::assert_abs_diff_eq!(459_309.209, on_meridian.y, epsilon = 0.01);
+
+        let warsaw = crs.project(Position::new(21., 52.));
+        approx::assert_abs_diff_eq!(637_253.161, warsaw.x, epsilon = 0.01);
+        approx::assert_abs_diff_eq!(461_197.243, warsaw.y, epsilon = 0.01);
+
+        for position in [
+            Position::new(21.00027, 52.26470),
+            Position::new(14.1, 49.),
+            Position::new(24.2, 54.9),
+        ] {
+            let calculated = crs.unproject(crs.project(position));
+            approx::assert_abs_diff_eq!(position.x(), calculated.x(), epsilon = 1e-7);
+            approx::assert_abs_diff_eq!(position.y(), calculated.y(), epsilon = 1e-7);
+        }
+    }
+}
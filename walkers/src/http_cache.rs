@@ -0,0 +1,347 @@
+//! HTTP caching semantics of the tiles, i.e. how long they are fresh and how to revalidate them
+//! once they are not. See <https://www.rfc-editor.org/rfc/rfc9111>.
+
+use reqwest::header::{
+    HeaderMap, AGE, CACHE_CONTROL, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+
+/// Revalidated tiles stay fresh at least this long (in seconds), even if the server says
+/// otherwise. Otherwise, tiles with `no-cache` would be revalidated at each frame.
+pub(crate) const MIN_FRESHNESS: u64 = 60;
+
+/// Caching information of a single tile, taken from the HTTP response headers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CachePolicy {
+    /// Unix time (in seconds) after which the tile needs to be revalidated. `None` means that
+    /// server did not say, in which case the tile never expires.
+    pub expires: Option<u64>,
+
+    /// How long (in seconds) the server said the tile stays fresh. It is used again when
+    /// revalidation response does not say it, as many servers send bare `304 Not Modified`.
+    pub lifetime: Option<u64>,
+
+    /// Validator sent back in `If-None-Match`.
+    pub etag: Option<String>,
+
+    /// Validator sent back in `If-Modified-Since`.
+    pub last_modified: Option<String>,
+
+    /// Tile must not be stored in the disk cache.
+    pub no_store: bool,
+}
+
+impl CachePolicy {
+    pub fn from_headers(headers: &HeaderMap, now: u64) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                .map(str::to_owned)
+        };
+
+        let mut max_age = None;
+        let mut no_store = false;
+
+        for directive in header(CACHE_CONTROL).unwrap_or_default().split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            if let Some(seconds) = directive.strip_prefix("max-age=") {
+                max_age = seconds.trim_matches('"').parse::<u64>().ok().or(Some(0));
+            } else if directive == "no-cache" {
+                max_age = Some(0);
+            } else if directive == "no-store" {
+                max_age = Some(0);
+                no_store = true;
+            }
+        }
+
+        let (expires, lifetime) = match max_age {
+            // Age is how long the response was already sitting in some proxy's cache.
+            Some(max_age) => {
+                let age = header(AGE)
+                    .and_then(|age| age.parse::<u64>().ok())
+                    .unwrap_or(0);
+                (
+                    Some(now.saturating_add(max_age).saturating_sub(age)),
+                    Some(max_age),
+                )
+            }
+            // Invalid dates, like "0", mean that it is already expired.
+            None => {
+                let expires = header(EXPIRES).map(|expires| {
+                    httpdate::parse_http_date(&expires)
+                        .ok()
+                        .and_then(|expires| expires.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map_or(0, |expires| expires.as_secs())
+                });
+                (expires, expires.map(|expires| expires.saturating_sub(now)))
+            }
+        };
+
+        Self {
+            expires,
+            lifetime,
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            no_store,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+
+    /// Make sure that the tile stays fresh for at least given number of seconds.
+    pub fn fresh_for_at_least(mut self, seconds: u64, now: u64) -> Self {
+        if let Some(expires) = self.expires {
+            self.expires = Some(expires.max(now.saturating_add(seconds)));
+        }
+        self
+    }
+
+    /// Update the policy with headers of a `304 Not Modified` response. Validators which were
+    /// not sent again are kept, and so is the freshness lifetime, unless the response has a new
+    /// one (RFC 9111, section 4.3.4).
+    pub fn refreshed(self, headers: &HeaderMap, now: u64) -> Self {
+        let fresh = Self::from_headers(headers, now);
+        let fresh = if headers.contains_key(CACHE_CONTROL) || headers.contains_key(EXPIRES) {
+            fresh
+        } else {
+            // Policies stored before the lifetime was known are revalidated again soon.
+            let lifetime = self.lifetime.unwrap_or(0);
+            Self {
+                expires: Some(now.saturating_add(lifetime)),
+                lifetime: Some(lifetime),
+                no_store: self.no_store,
+                ..fresh
+            }
+        };
+
+        Self {
+            etag: fresh.etag.or(self.etag),
+            last_modified: fresh.last_modified.or(self.last_modified),
+            ..fresh
+        }
+    }
+
+    /// Add conditional headers to the request, so server can respond with `304 Not Modified`.
+    pub fn conditional(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+
+    /// Simple, line based format used by the disk cache.
+    pub fn serialize(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(expires) = self.expires {
+            lines.push(format!("expires {}", expires));
+        }
+        if let Some(lifetime) = self.lifetime {
+            lines.push(format!("lifetime {}", lifetime));
+        }
+        if let Some(etag) = &self.etag {
+            lines.push(format!("etag {}", etag));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            lines.push(format!("last-modified {}", last_modified));
+        }
+        lines.join("\n")
+    }
+
+    pub fn deserialize(text: &str) -> Self {
+        let mut policy = Self::default();
+        for line in text.lines() {
+            match line.split_once(' ') {
+                Some(("expires", expires)) => policy.expires = expires.parse().ok(),
+                Some(("lifetime", lifetime)) => policy.lifetime = lifetime.parse().ok(),
+                Some(("etag", etag)) => policy.etag = Some(etag.to_owned()),
+                Some(("last-modified", last_modified)) => {
+                    policy.last_modified = Some(last_modified.to_owned())
+                }
+                _ => log::debug!("Ignoring unknown cache policy line: {}", line),
+            }
+        }
+        policy
+    }
+}
+
+/// Current Unix time, in seconds.
+pub(crate) fn now() -> u64 {
+    web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const NOW: u64 = 1_000_000;
+
+    fn headers(headers: &[(reqwest::header::HeaderName, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn tile_without_caching_headers_never_expires() {
+        let policy = CachePolicy::from_headers(&HeaderMap::new(), NOW);
+        assert_eq!(CachePolicy::default(), policy);
+        assert!(!policy.is_expired(u64::MAX));
+    }
+
+    #[test]
+    fn huge_max_age_does_not_overflow() {
+        let policy = CachePolicy::from_headers(
+            &headers(&[(CACHE_CONTROL, "max-age=18446744073709551615")]),
+            NOW,
+        );
+        assert_eq!(Some(u64::MAX), policy.expires);
+        assert_eq!(Some(u64::MAX), policy.lifetime);
+        assert!(!policy.is_expired(u64::MAX - 1));
+
+        let refreshed = policy.refreshed(&HeaderMap::new(), NOW);
+        assert_eq!(Some(u64::MAX), refreshed.expires);
+    }
+
+    #[test]
+    fn max_age_takes_age_into_account() {
+        let policy = CachePolicy::from_headers(
+            &headers(&[(CACHE_CONTROL, "public, max-age=3600"), (AGE, "600")]),
+            NOW,
+        );
+
+        assert_eq!(Some(NOW + 3000), policy.expires);
+        assert!(!policy.is_expired(NOW + 2999));
+        assert!(policy.is_expired(NOW + 3000));
+    }
+
+    #[test]
+    fn max_age_takes_precedence_over_expires() {
+        let policy = CachePolicy::from_headers(
+            &headers(&[
+                (EXPIRES, "Thu, 01 Jan 1970 00:00:00 GMT"),
+                (CACHE_CONTROL, "max-age=10"),
+            ]),
+            NOW,
+        );
+        assert_eq!(Some(NOW + 10), policy.expires);
+    }
+
+    #[test]
+    fn expires_header() {
+        let policy =
+            CachePolicy::from_headers(&headers(&[(EXPIRES, "Sun, 06 Nov 1994 08:49:37 GMT")]), NOW);
+        assert_eq!(Some(784111777), policy.expires);
+
+        let policy = CachePolicy::from_headers(&headers(&[(EXPIRES, "0")]), NOW);
+        assert!(policy.is_expired(NOW));
+    }
+
+    #[test]
+    fn no_cache_and_no_store() {
+        let policy = CachePolicy::from_headers(&headers(&[(CACHE_CONTROL, "no-cache")]), NOW);
+        assert!(policy.is_expired(NOW));
+        assert!(!policy.no_store);
+
+        let policy = CachePolicy::from_headers(&headers(&[(CACHE_CONTROL, "no-store")]), NOW);
+        assert!(policy.is_expired(NOW));
+        assert!(policy.no_store);
+    }
+
+    #[test]
+    fn refreshing_keeps_validators() {
+        let policy = CachePolicy::from_headers(
+            &headers(&[
+                (CACHE_CONTROL, "max-age=0"),
+                (ETAG, "\"abc\""),
+                (LAST_MODIFIED, "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ]),
+            NOW,
+        );
+
+        let refreshed = policy
+            .clone()
+            .refreshed(&headers(&[(CACHE_CONTROL, "max-age=100")]), NOW);
+
+        assert_eq!(Some(NOW + 100), refreshed.expires);
+        assert_eq!(policy.etag, refreshed.etag);
+        assert_eq!(policy.last_modified, refreshed.last_modified);
+    }
+
+    #[test]
+    fn refreshing_without_caching_headers_keeps_the_lifetime() {
+        let policy = CachePolicy::from_headers(
+            &headers(&[(CACHE_CONTROL, "max-age=3600"), (ETAG, "\"abc\"")]),
+            NOW,
+        );
+        assert_eq!(Some(3600), policy.lifetime);
+
+        // Many servers send just the validator with `304 Not Modified`.
+        let later = NOW + 7200;
+        let refreshed = policy
+            .clone()
+            .refreshed(&headers(&[(ETAG, "\"abc\"")]), later);
+        assert_eq!(Some(later + 3600), refreshed.expires);
+        assert_eq!(policy.etag, refreshed.etag);
+
+        // Lifetime given by Expires is kept too.
+        let policy = CachePolicy::from_headers(
+            &headers(&[(EXPIRES, "Sun, 06 Nov 1994 08:49:37 GMT")]),
+            784111000,
+        );
+        assert_eq!(Some(777), policy.lifetime);
+        assert_eq!(
+            Some(NOW + 777),
+            policy.refreshed(&HeaderMap::new(), NOW).expires
+        );
+
+        // Without known lifetime, the tile is revalidated again soon.
+        let policy = CachePolicy {
+            expires: Some(NOW),
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(NOW + MIN_FRESHNESS),
+            policy
+                .refreshed(&HeaderMap::new(), NOW)
+                .fresh_for_at_least(MIN_FRESHNESS, NOW)
+                .expires
+        );
+    }
+
+    #[test]
+    fn minimum_freshness() {
+        let policy = CachePolicy::from_headers(&headers(&[(CACHE_CONTROL, "no-cache")]), NOW)
+            .fresh_for_at_least(MIN_FRESHNESS, NOW);
+        assert_eq!(Some(NOW + MIN_FRESHNESS), policy.expires);
+
+        // Tiles which never expire stay this way.
+        let policy = CachePolicy::default().fresh_for_at_least(MIN_FRESHNESS, NOW);
+        assert_eq!(None, policy.expires);
+    }
+
+    #[test]
+    fn serialization() {
+        let policy = CachePolicy {
+            expires: Some(NOW),
+            lifetime: Some(3600),
+            etag: Some("W/\"a b\"".to_owned()),
+            last_modified: Some("Sun, 06 Nov 1994 08:49:37 GMT".to_owned()),
+            no_store: false,
+        };
+
+        assert_eq!(policy, CachePolicy::deserialize(&policy.serialize()));
+        assert_eq!(
+            CachePolicy::default(),
+            CachePolicy::deserialize(&CachePolicy::default().serialize())
+        );
+    }
+}
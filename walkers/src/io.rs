@@ -1,5 +1,6 @@
 //! Managed thread for Tokio runtime.
 use std::future::Future;
+use std::time::Duration;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub use native::TokioRuntimeThread as Runtime;
@@ -7,6 +8,28 @@ pub use native::TokioRuntimeThread as Runtime;
 #[cfg(target_arch = "wasm32")]
 pub use web::WasmBindgenFutures as Runtime;
 
+/// Wait asynchronously, using whatever timer the runtime provides.
+pub async fn sleep(duration: Duration) {
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(duration).await;
+
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(duration).await;
+}
+
+/// Run blocking code, such as file operations, without stalling other futures of the runtime.
+/// Returns `None` if it panicked.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    tokio::task::spawn_blocking(f).await.ok()
+}
+
+/// There are no threads on the web, so the code just runs.
+#[cfg(target_arch = "wasm32")]
+pub async fn blocking<T>(f: impl FnOnce() -> T) -> Option<T> {
+    Some(f())
+}
+
 #[cfg(target_arch = "wasm32")]
 mod web {
     use super::*;
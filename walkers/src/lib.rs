@@ -1,16 +1,32 @@
 #![doc = include_str!("../README.md")]
 #![deny(clippy::unwrap_used, rustdoc::broken_intra_doc_links)]
 
+mod cache;
+mod disk_cache;
 mod download;
 pub mod extras;
+mod grid;
+mod http_cache;
 mod io;
 mod map;
+#[cfg(all(feature = "mbtiles", not(target_family = "wasm")))]
+mod mbtiles;
 mod mercator;
 pub mod providers;
 mod tiles;
+#[cfg(feature = "mvt")]
+mod vector;
 mod zoom;
 
-pub use map::{Center, Map, MapMemory, Plugin, Projector};
+pub use cache::{CacheStats, Capacity};
+pub use disk_cache::DiskCache;
+pub use download::{HttpFetcher, HttpOptions, Retry};
+pub use grid::{Crs, Level, TileGrid, TransverseMercator, WebMercator, Wgs84};
+pub use map::{Center, Layer, Map, MapMemory, Plugin, Projector, ZoomAnchor};
+#[cfg(all(feature = "mbtiles", not(target_family = "wasm")))]
+pub use mbtiles::{MbTiles, MbTilesError, Metadata};
 pub use mercator::{screen_to_position, Position, PositionExt};
-pub use tiles::Tiles;
+pub use tiles::{Status, TileState, Tiles, TilesOptions};
+#[cfg(feature = "mvt")]
+pub use vector::{LayerStyle, VectorStyle};
 pub use zoom::Zoom;
@@ -1,9 +1,14 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::BTreeMap;
 
-use egui::{Context, Mesh, Painter, Pos2, Rect, Response, Sense, Ui, Vec2, Widget};
+use egui::{
+    emath::Rot2, Align2, Area, Context, Event, Frame, Id, Painter, Pos2, Rect, Response, Sense,
+    Shape, TouchPhase, Ui, Vec2, Widget,
+};
 
 use crate::{
-    mercator::{screen_to_position, PositionExt, TileId},
+    grid::{screen_offset, TileGrid},
+    mercator::TileId,
+    providers::Attribution,
     Position, Tiles, Zoom,
 };
 
@@ -12,6 +17,12 @@ use crate::{
 pub trait Plugin {
     /// Function called at each frame.
     fn draw(&self, painter: Painter, projector: &Projector);
+
+    /// Function called at each frame, before the map is drawn. It allows handling the user's
+    /// input within the map's `response` and changing its state, e.g. the bearing.
+    fn interact(&self, ui: &mut Ui, response: &Response, memory: &mut MapMemory) {
+        let _ = (ui, response, memory);
+    }
 }
 
 /// The actual map widget. Instances are to be created on each frame, as all necessary state is
@@ -31,10 +42,14 @@ pub trait Plugin {
 /// }
 /// ```
 pub struct Map<'a, 'b> {
-    tiles: Option<&'b mut Tiles>,
+    layers: Vec<Layer<'b>>,
     memory: &'a mut MapMemory,
     my_position: Position,
     plugins: Vec<Box<dyn Plugin>>,
+    zoom_anchor: ZoomAnchor,
+    attribution: Option<Align2>,
+    grid: TileGrid,
+    rotate_gesture: bool,
 }
 
 impl<'a, 'b> Map<'a, 'b> {
@@ -44,18 +59,149 @@ impl<'a, 'b> Map<'a, 'b> {
         my_position: Position,
     ) -> Self {
         Self {
-            tiles,
+            layers: tiles.map(Layer::new).into_iter().collect(),
             memory,
             my_position,
             plugins: Vec::default(),
+            zoom_anchor: ZoomAnchor::default(),
+            attribution: None,
+            grid: TileGrid::default(),
+            rotate_gesture: true,
         }
     }
 
+    /// Draw another layer of tiles over the ones given in [`Map::new`] and the previously added
+    /// layers, e.g. a semi-transparent hillshade or weather radar.
+    ///
+    /// ```
+    /// # use walkers::{Layer, Map, Tiles, MapMemory, Position};
+    /// fn update(ui: &mut egui::Ui, base: &mut Tiles, radar: &mut Tiles, map_memory: &mut MapMemory) {
+    ///     ui.add(
+    ///         Map::new(Some(base), map_memory, Position::new(17.03664, 51.09916))
+    ///             .with_layer(Layer::new(radar).with_opacity(0.5)),
+    ///     );
+    /// }
+    /// ```
+    pub fn with_layer(mut self, layer: Layer<'b>) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
     /// Add plugin to the drawing pipeline. Plugins allow drawing custom shaped on the map.
     pub fn with_plugin(mut self, plugin: impl Plugin + 'static) -> Self {
         self.plugins.push(Box::new(plugin));
         self
     }
+
+    /// Choose the point which stays in place while zooming with the mouse wheel or a pinch
+    /// gesture. See [`ZoomAnchor`].
+    pub fn with_zoom_anchor(mut self, zoom_anchor: ZoomAnchor) -> Self {
+        self.zoom_anchor = zoom_anchor;
+        self
+    }
+
+    /// Show the attribution of the tiles in the given corner of the map. Entries which are not
+    /// [`Attribution::required`] are collapsed behind a button.
+    pub fn with_attribution(mut self, corner: Align2) -> Self {
+        self.attribution = Some(corner);
+        self
+    }
+
+    /// Grid defining the map's CRS, and its resolution at each zoom level. Default is the Web
+    /// Mercator with 256 pixel tiles. Only the layers in the same CRS are drawn, so it typically
+    /// is the grid of the base layer, e.g. one of the national services.
+    ///
+    /// ```
+    /// # use walkers::{Map, Tiles, MapMemory, Position};
+    /// fn update(ui: &mut egui::Ui, tiles: &mut Tiles, map_memory: &mut MapMemory) {
+    ///     let grid = tiles.grid().clone();
+    ///     ui.add(
+    ///         Map::new(Some(tiles), map_memory, Position::new(17.03664, 51.09916))
+    ///             .with_grid(grid),
+    ///     );
+    /// }
+    /// ```
+    pub fn with_grid(mut self, grid: TileGrid) -> Self {
+        self.grid = grid;
+        self
+    }
+
+    /// Whether the map can be rotated with the two-finger gesture, which changes
+    /// [`MapMemory::bearing`]. Enabled by default.
+    pub fn with_rotate_gesture(mut self, enabled: bool) -> Self {
+        self.rotate_gesture = enabled;
+        self
+    }
+}
+
+/// Tiles drawn by the [`Map`], see [`Map::with_layer`].
+pub struct Layer<'b> {
+    tiles: &'b mut Tiles,
+    opacity: f32,
+    visible: bool,
+}
+
+impl<'b> Layer<'b> {
+    /// Fully opaque, visible layer.
+    pub fn new(tiles: &'b mut Tiles) -> Self {
+        Self {
+            tiles,
+            opacity: 1.,
+            visible: true,
+        }
+    }
+
+    /// Opacity of the layer, from `0.0` (transparent) to `1.0` (opaque).
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0., 1.);
+        self
+    }
+
+    /// Hidden layers are neither drawn, nor downloaded, and their attribution is not shown.
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+}
+
+/// Keeps track of fingers touching the screen, in egui's temporary memory under the given `id`.
+/// Returns the point between them, if there are at least two.
+fn track_touches(ui: &Ui, id: Id) -> Option<Pos2> {
+    let mut touches: BTreeMap<u64, Pos2> = ui.data(|data| data.get_temp(id).unwrap_or_default());
+
+    ui.input(|input| {
+        for event in &input.events {
+            if let Event::Touch { id, phase, pos, .. } = event {
+                match phase {
+                    TouchPhase::Start | TouchPhase::Move => touches.insert(id.0, *pos),
+                    TouchPhase::End | TouchPhase::Cancel => touches.remove(&id.0),
+                };
+            }
+        }
+    });
+
+    let center = (touches.len() >= 2).then(|| {
+        let sum = touches
+            .values()
+            .fold(Vec2::ZERO, |sum, pos| sum + pos.to_vec2());
+        (sum / touches.len() as f32).to_pos2()
+    });
+
+    ui.data_mut(|data| data.insert_temp(id, touches));
+    center
+}
+
+/// Point of the map which stays in place while zooming.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZoomAnchor {
+    /// Map's center stays in place, therefore [`MapMemory::center_mode`] is not changed by
+    /// zooming. This means that the map keeps following `my_position`.
+    #[default]
+    Center,
+
+    /// Geographical position under the mouse pointer, or between the fingers doing the pinch
+    /// gesture, stays in place. Since this moves the map's center, the map gets detached.
+    Pointer,
 }
 
 /// Projects geographical position into screen pixels, suitable for [`egui::Painter`].
@@ -63,22 +209,32 @@ pub struct Projector<'a> {
     clip_rect: Rect,
     memory: &'a MapMemory,
     my_position: Position,
+    grid: &'a TileGrid,
 }
 
 impl<'a> Projector<'a> {
     pub fn project(&self, position: Position) -> Vec2 {
-        // Turn that into a flat, mercator projection.
-        let projected_position = position.project(self.memory.zoom.round());
+        let map_center = self.memory.center_mode.position(self.my_position);
 
-        // We also need to know where the map center is.
-        let map_center_projected_position = self
-            .memory
-            .center_mode
-            .position(self.my_position)
-            .project(self.memory.zoom.round());
+        // Offset from the map's center, in the map's CRS, gives the actual point on the screen,
+        // once turned according to the bearing.
+        let offset = self
+            .grid
+            .offset(map_center, position, self.memory.zoom.into());
+        self.clip_rect.center().to_vec2() + rotation(self.memory.bearing) * offset
+    }
+
+    /// Geographical position at the given point of the screen.
+    pub fn unproject(&self, position: Pos2) -> Position {
+        let map_center = self.memory.center_mode.position(self.my_position);
+        let offset = rotation(self.memory.bearing).inverse() * (position - self.clip_rect.center());
+        self.grid
+            .position_at(map_center, offset, self.memory.zoom.into())
+    }
 
-        // From the two points above we can calculate the actual point on the screen.
-        self.clip_rect.center() + projected_position.to_vec2() - map_center_projected_position
+    /// Bearing of the map, see [`MapMemory::bearing`].
+    pub fn bearing(&self) -> f32 {
+        self.memory.bearing
     }
 }
 
@@ -86,43 +242,101 @@ impl Widget for Map<'_, '_> {
     fn ui(self, ui: &mut Ui) -> Response {
         let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
 
-        let zoom_delta = ui.input(|input| input.zoom_delta());
+        let touches = track_touches(ui, response.id.with("touches"));
+        let (zoom_delta, rotation_delta, pointer) = ui.input(|input| {
+            (
+                input.zoom_delta(),
+                input
+                    .multi_touch()
+                    .map_or(0., |multi_touch| multi_touch.rotation_delta),
+                touches.or(input.pointer.hover_pos()),
+            )
+        });
+
+        if self.rotate_gesture && rotation_delta != 0. {
+            // Map turns along with the fingers, i.e. clockwise when the bearing decreases.
+            self.memory.bearing =
+                (self.memory.bearing - rotation_delta.to_degrees()).rem_euclid(360.);
+        }
 
         // Zooming and dragging need to be exclusive, otherwise the map will get dragged when
         // pinch gesture is used.
         if !(0.99..=1.01).contains(&zoom_delta) {
             // Shift by 1 because of the values given by zoom_delta(). Multiple by 2, because
             // then it felt right with both mouse wheel, and an Android phone.
-            self.memory.zoom.zoom_by((zoom_delta - 1.) * 2.);
+            let zoom_delta = (zoom_delta - 1.) * 2.;
+
+            match (self.zoom_anchor, pointer) {
+                (ZoomAnchor::Pointer, Some(pointer)) if rect.contains(pointer) => {
+                    self.memory.zoom_around(
+                        zoom_delta,
+                        pointer - rect.center(),
+                        self.my_position,
+                        &self.grid,
+                    );
+                }
+                _ => self.memory.zoom.zoom_by(zoom_delta),
+            }
         } else {
-            self.memory
-                .center_mode
-                .recalculate_drag(&response, self.my_position);
+            self.memory.center_mode.recalculate_drag(
+                &response,
+                self.my_position,
+                self.memory.bearing,
+            );
+        }
+
+        for plugin in &self.plugins {
+            plugin.interact(ui, &response, self.memory);
         }
 
         self.memory.center_mode.recalculate_inertial_movement(
             ui.ctx(),
             self.my_position,
-            self.memory.zoom.round(),
+            self.memory.zoom.into(),
+            &self.grid,
         );
 
         let map_center = self.memory.center_mode.position(self.my_position);
         let painter = ui.painter().with_clip_rect(rect);
+        let resolution = self.grid.resolution(self.memory.zoom.into());
 
-        if let Some(tiles) = self.tiles {
-            let mut meshes = Default::default();
-            draw_tiles(
+        let mut attributions: Vec<Attribution> = Vec::new();
+
+        // Layers are drawn in order, so the last one ends up on the top.
+        for layer in self.layers.into_iter().filter(|layer| layer.visible) {
+            if !layer.tiles.grid().same_crs(&self.grid) {
+                // Map is drawn at each frame, but it is enough to tell about it once.
+                let code = layer.tiles.grid().crs().code();
+                let warned_id = response.id.with(("different crs", code));
+                if !ui.data(|data| data.get_temp(warned_id).unwrap_or(false)) {
+                    log::warn!(
+                        "Layer in {} can not be drawn on a map in {}.",
+                        code,
+                        self.grid.crs().code()
+                    );
+                    ui.data_mut(|data| data.insert_temp(warned_id, true));
+                }
+                continue;
+            }
+
+            // Layers often share the provider, e.g. OpenStreetMap.
+            for attribution in layer.tiles.attributions() {
+                if !attributions.contains(attribution) {
+                    attributions.push(attribution.clone());
+                }
+            }
+
+            let shapes = draw_tiles(
                 &painter,
-                map_center.tile_id(self.memory.zoom.round()),
-                map_center.project(self.memory.zoom.round()),
-                tiles,
-                ui,
-                &mut meshes,
+                self.grid.crs().project(map_center),
+                resolution,
+                self.memory.bearing,
+                layer.tiles,
+                layer.opacity,
+                ui.ctx(),
             );
 
-            for (_, shape) in meshes {
-                painter.add(shape);
-            }
+            painter.extend(shapes);
         }
 
         for plugin in self.plugins {
@@ -132,11 +346,16 @@ impl Widget for Map<'_, '_> {
                 clip_rect: response.rect,
                 memory: self.memory,
                 my_position: self.my_position,
+                grid: &self.grid,
             };
 
             plugin.draw(painter, &projector);
         }
 
+        if let Some(corner) = self.attribution {
+            draw_attribution(ui, response.id, rect, corner, &attributions);
+        }
+
         response
     }
 }
@@ -163,17 +382,24 @@ pub enum Center {
 }
 
 impl Center {
-    fn recalculate_drag(&mut self, response: &Response, my_position: Position) {
+    fn recalculate_drag(&mut self, response: &Response, my_position: Position, bearing: f32) {
         if response.dragged_by(egui::PointerButton::Primary) {
             *self = Center::Inertia {
                 position: self.position(my_position),
-                direction: response.drag_delta(),
+                // Direction on the north-up map, so it stays the same when the map rotates.
+                direction: rotation(bearing).inverse() * response.drag_delta(),
                 amount: 1.0,
             };
         }
     }
 
-    fn recalculate_inertial_movement(&mut self, ctx: &Context, my_position: Position, zoom: u8) {
+    fn recalculate_inertial_movement(
+        &mut self,
+        ctx: &Context,
+        my_position: Position,
+        zoom: f64,
+        grid: &TileGrid,
+    ) {
         if let Center::Inertia {
             position,
             direction,
@@ -184,8 +410,9 @@ impl Center {
                 Center::Exact(*position)
             } else {
                 Center::Inertia {
-                    position: screen_to_position(
-                        self.position(my_position).project(zoom) - (*direction * *amount),
+                    position: grid.position_at(
+                        self.position(my_position),
+                        -(*direction * *amount),
                         zoom,
                     ),
                     direction: *direction,
@@ -224,49 +451,648 @@ impl Center {
 pub struct MapMemory {
     pub center_mode: Center,
     pub zoom: Zoom,
+
+    /// Direction the top of the map is facing, in degrees clockwise from the north. Setting it to
+    /// the heading of a vehicle gives the heading-up mode.
+    pub bearing: f32,
+}
+
+impl MapMemory {
+    /// Zoom by a relative value, keeping the geographical position at given offset (in screen
+    /// pixels) from the map's center in place.
+    fn zoom_around(
+        &mut self,
+        zoom_delta: f32,
+        offset: Vec2,
+        my_position: Position,
+        grid: &TileGrid,
+    ) {
+        let offset = rotation(self.bearing).inverse() * offset;
+        let center = self.center_mode.position(my_position);
+        let anchor = grid.position_at(center, offset, self.zoom.into());
+
+        self.zoom.zoom_by(zoom_delta);
+
+        self.center_mode = Center::Exact(grid.position_at(anchor, -offset, self.zoom.into()));
+    }
 }
 
+/// Rotation of the offsets on the north-up map into the ones on the screen, given the bearing.
+fn rotation(bearing: f32) -> Rot2 {
+    Rot2::from_angle(-bearing.to_radians())
+}
+
+/// Overlay with the attribution entries, placed in the corner of the map's `rect`.
+fn draw_attribution(ui: &Ui, id: Id, rect: Rect, corner: Align2, attributions: &[Attribution]) {
+    if attributions.is_empty() {
+        return;
+    }
+
+    let id = id.with("attribution");
+    let expanded_id = id.with("expanded");
+    let expanded = ui.data(|data| data.get_temp(expanded_id).unwrap_or(false));
+    let collapsible = attributions.iter().any(|attribution| !attribution.required);
+
+    Area::new(id)
+        .pivot(corner)
+        .fixed_pos(corner.pos_in_rect(&rect.shrink(4.)))
+        .show(ui.ctx(), |ui| {
+            Frame::none()
+                .fill(ui.visuals().extreme_bg_color.gamma_multiply(0.8))
+                .rounding(4.)
+                .inner_margin(4.)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        let height = ui.text_style_height(&egui::TextStyle::Body);
+
+                        for attribution in attributions
+                            .iter()
+                            .filter(|attribution| attribution.required || expanded)
+                        {
+                            if let Some(logo) = &attribution.logo {
+                                logo.show(ui, height);
+                            }
+
+                            if attribution.url.is_empty() {
+                                ui.label(&attribution.text);
+                            } else {
+                                ui.hyperlink_to(&attribution.text, &attribution.url);
+                            }
+                        }
+
+                        if collapsible {
+                            let button = if expanded { "⏴" } else { "ℹ" };
+                            if ui.small_button(button).clicked() {
+                                ui.data_mut(|data| data.insert_temp(expanded_id, !expanded));
+                            }
+                        }
+                    });
+                });
+        });
+}
+
+/// Shapes of the tiles covering the painter's clip rect, which is centered at given point of the
+/// CRS, and drawn at given resolution and bearing.
 fn draw_tiles(
     painter: &Painter,
-    tile_id: TileId,
-    map_center_projected_position: Pos2,
+    center: geo_types::Coord,
+    resolution: f64,
+    bearing: f32,
     tiles: &mut Tiles,
-    ui: &mut Ui,
-    meshes: &mut HashMap<TileId, Mesh>,
-) {
-    let tile_projected = tile_id.project();
-    let tile_screen_position = painter.clip_rect().center().to_vec2() + tile_projected.to_vec2()
-        - map_center_projected_position.to_vec2();
-
-    let Some(image) = tiles.at(tile_id) else {
-        return;
+    opacity: f32,
+    ctx: &Context,
+) -> Vec<Shape> {
+    let clip_rect = painter.clip_rect();
+    let mut shapes = Vec::new();
+
+    // Tiles are taken from the level of the closest resolution, and then scaled to match the
+    // actual, fractional zoom.
+    let grid = tiles.grid().clone();
+    let Some(level) = grid.level(resolution) else {
+        return shapes;
     };
 
-    if painter
-        .clip_rect()
-        .intersects(image.rect(tile_screen_position))
-    {
-        if let Entry::Vacant(vacant) = meshes.entry(tile_id) {
-            vacant.insert(image.mesh(tile_screen_position, ui.ctx()));
-
-            for coordinates in [
-                tile_id.north(),
-                tile_id.east(),
-                tile_id.south(),
-                tile_id.west(),
-            ]
-            .iter()
-            .flatten()
+    // Rotated map needs to cover the bounding box of the viewport.
+    let (sin, cos) = bearing.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let size = clip_rect.size();
+    let viewport = Vec2::new(size.x * cos + size.y * sin, size.x * sin + size.y * cos);
+
+    // Tiles are sorted by the distance from the center, so these get requested first.
+    let visible = grid.visible_tiles(center, viewport, resolution, level);
+
+    // Requests for tiles which are not visible anymore are dropped by the IO thread.
+    tiles.set_viewport(visible.iter().copied());
+    tiles.set_pixels_per_point(ctx.pixels_per_point());
+
+    for tile_id in visible {
+        if let Some(bounding_box) = grid.tile_bounding_box(tile_id) {
+            let tile_rect = Rect::from_min_max(
+                clip_rect.center()
+                    + screen_offset(
+                        center,
+                        geo_types::coord! { x: bounding_box.min().x, y: bounding_box.max().y },
+                        resolution,
+                    ),
+                clip_rect.center()
+                    + screen_offset(
+                        center,
+                        geo_types::coord! { x: bounding_box.max().x, y: bounding_box.min().y },
+                        resolution,
+                    ),
+            );
+
+            shapes.extend(tile_shapes(
+                &grid,
+                tile_id,
+                tile_rect,
+                tiles,
+                opacity < 1.,
+                ctx,
+            ));
+        }
+    }
+
+    if bearing != 0. {
+        let rotation = rotation(bearing);
+        let rotate =
+            |position: Pos2| clip_rect.center() + rotation * (position - clip_rect.center());
+
+        for shape in &mut shapes {
+            match shape {
+                Shape::Mesh(mesh) => {
+                    for vertex in &mut mesh.vertices {
+                        vertex.pos = rotate(vertex.pos);
+                    }
+                }
+                Shape::Text(text) => {
+                    // Labels stay upright, only their centers are moved.
+                    let half = text.galley.size() / 2.;
+                    text.pos = rotate(text.pos + half) - half;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if opacity < 1. {
+        // Colors use premultiplied alpha, so all the channels are scaled.
+        for shape in &mut shapes {
+            match shape {
+                Shape::Mesh(mesh) => {
+                    for vertex in &mut mesh.vertices {
+                        vertex.color = vertex.color.gamma_multiply(opacity);
+                    }
+                }
+                Shape::Text(text) => {
+                    if let Some(color) = &mut text.override_text_color {
+                        *color = color.gamma_multiply(opacity);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    shapes
+}
+
+/// Shapes covering the tile's rectangle. If the tile is not available yet, its already cached
+/// ancestor and children are used instead, so there is no empty space where lower (or higher)
+/// detail imagery is available. Shapes of a `translucent` layer must not overlap, as they would
+/// blend with each other, so children are not used together with the ancestor.
+fn tile_shapes(
+    grid: &TileGrid,
+    tile_id: TileId,
+    rect: Rect,
+    tiles: &mut Tiles,
+    translucent: bool,
+    ctx: &Context,
+) -> Vec<Shape> {
+    let full_uv = Rect::from_min_max(Pos2::new(0., 0.), Pos2::new(1., 1.));
+
+    if let Some(tile) = tiles.at(tile_id) {
+        return tile.shapes(rect, full_uv, ctx);
+    }
+
+    let mut shapes = Vec::new();
+
+    // Upscaled part of an ancestor goes first, so children can cover it with more details.
+    let mut ancestor = tile_id;
+    while let Some(parent) = grid.parent(ancestor) {
+        ancestor = parent;
+        if let Some(tile) = tiles.cached(ancestor) {
+            if let Some(uv) = ancestor_uv(grid, tile_id, ancestor) {
+                shapes.extend(tile.shapes(rect, uv, ctx));
+            }
+            break;
+        }
+    }
+
+    if translucent && !shapes.is_empty() {
+        return shapes;
+    }
+
+    if let Some(bounding_box) = grid.tile_bounding_box(tile_id) {
+        for child in grid.children(tile_id) {
+            if let (Some(tile), Some(child_box)) =
+                (tiles.cached(child), grid.tile_bounding_box(child))
             {
-                draw_tiles(
-                    painter,
-                    *coordinates,
-                    map_center_projected_position,
-                    tiles,
-                    ui,
-                    meshes,
-                );
+                let child_rect = sub_rect(rect, bounding_box, child_box);
+                shapes.extend(tile.shapes(child_rect, full_uv, ctx));
+            }
+        }
+    }
+
+    shapes
+}
+
+/// Part of the ancestor's image (in UV coordinates) which covers given tile.
+fn ancestor_uv(grid: &TileGrid, tile_id: TileId, ancestor: TileId) -> Option<Rect> {
+    Some(sub_rect(
+        Rect::from_min_max(Pos2::new(0., 0.), Pos2::new(1., 1.)),
+        grid.tile_bounding_box(ancestor)?,
+        grid.tile_bounding_box(tile_id)?,
+    ))
+}
+
+/// Part of the `rect` showing the `inner` area, when the whole `rect` shows the `outer` one (both
+/// in the units of the CRS).
+fn sub_rect(rect: Rect, outer: geo_types::Rect, inner: geo_types::Rect) -> Rect {
+    let point = |x: f64, y: f64| {
+        rect.min
+            + Vec2::new(
+                ((x - outer.min().x) / outer.width()) as f32 * rect.width(),
+                ((outer.max().y - y) / outer.height()) as f32 * rect.height(),
+            )
+    };
+
+    Rect::from_min_max(
+        point(inner.min().x, inner.max().y),
+        point(inner.max().x, inner.min().y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mercator::{screen_to_position, PositionExt};
+    use egui::Color32;
+
+    #[test]
+    fn zooming_around_an_offset_keeps_its_position_in_place() {
+        let my_position = Position::new(17.03664, 51.09916);
+        let offset = Vec2::new(100., -50.);
+
+        let mut memory = MapMemory::default();
+        let position_under_pointer = |memory: &MapMemory| {
+            let zoom = memory.zoom.into();
+            let center = memory.center_mode.position(my_position);
+            screen_to_position(center.project(zoom) + offset, zoom)
+        };
+
+        let before = position_under_pointer(&memory);
+        memory.zoom_around(0.7, offset, my_position, &TileGrid::default());
+        let after = position_under_pointer(&memory);
+
+        assert!(memory.center_mode.detached().is_some());
+        approx::assert_relative_eq!(before.x(), after.x(), max_relative = 0.00001);
+        approx::assert_relative_eq!(before.y(), after.y(), max_relative = 0.00001);
+    }
+
+    #[test]
+    fn zooming_around_the_center_does_not_move_it() {
+        let my_position = Position::new(17.03664, 51.09916);
+
+        let mut memory = MapMemory::default();
+        memory.zoom_around(-1.2, Vec2::ZERO, my_position, &TileGrid::default());
+
+        let center = memory.center_mode.position(my_position);
+        approx::assert_relative_eq!(my_position.x(), center.x(), max_relative = 0.00001);
+        approx::assert_relative_eq!(my_position.y(), center.y(), max_relative = 0.00001);
+    }
+
+    #[test]
+    fn zooming_around_an_offset_of_a_rotated_map() {
+        let my_position = Position::new(17.03664, 51.09916);
+        let grid = TileGrid::default();
+        let clip_rect = Rect::from_min_size(Pos2::ZERO, Vec2::splat(200.));
+        let pointer = Pos2::new(150., 80.);
+
+        let mut memory = MapMemory {
+            bearing: 30.,
+            ..Default::default()
+        };
+        let position_under_pointer = |memory: &MapMemory| {
+            Projector {
+                clip_rect,
+                memory,
+                my_position,
+                grid: &grid,
+            }
+            .unproject(pointer)
+        };
+
+        let before = position_under_pointer(&memory);
+        memory.zoom_around(0.7, pointer - clip_rect.center(), my_position, &grid);
+        let after = position_under_pointer(&memory);
+
+        approx::assert_relative_eq!(before.x(), after.x(), max_relative = 0.00001);
+        approx::assert_relative_eq!(before.y(), after.y(), max_relative = 0.00001);
+    }
+
+    #[test]
+    fn projector_honors_the_bearing() {
+        let my_position = Position::new(17.03664, 51.09916);
+        let grid = TileGrid::default();
+        let memory = MapMemory {
+            bearing: 90.,
+            ..Default::default()
+        };
+        let projector = Projector {
+            clip_rect: Rect::from_min_size(Pos2::ZERO, Vec2::splat(200.)),
+            memory: &memory,
+            my_position,
+            grid: &grid,
+        };
+
+        // Map is facing the east, so the north is on the left.
+        let north = projector.project(Position::new(my_position.x(), my_position.y() + 0.001));
+        assert!(north.x < 90.);
+        approx::assert_relative_eq!(100., north.y, max_relative = 0.0001);
+
+        let position = Position::new(17.04, 51.1);
+        let calculated = projector.unproject(projector.project(position).to_pos2());
+        approx::assert_relative_eq!(position.x(), calculated.x(), max_relative = 0.00001);
+        approx::assert_relative_eq!(position.y(), calculated.y(), max_relative = 0.00001);
+    }
+
+    #[test]
+    fn pinch_center_follows_the_fingers() {
+        let ctx = Context::default();
+        let id = Id::new("touches");
+        let touch = |id, phase, pos| Event::Touch {
+            device_id: egui::TouchDeviceId(0),
+            id: egui::TouchId(id),
+            phase,
+            pos,
+            force: 0.,
+        };
+
+        let center_after = |events| {
+            let mut center = None;
+            let _ = ctx.run(
+                egui::RawInput {
+                    events,
+                    ..Default::default()
+                },
+                |ctx| {
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        center = track_touches(ui, id);
+                    });
+                },
+            );
+            center
+        };
+
+        assert_eq!(
+            None,
+            center_after(vec![touch(0, TouchPhase::Start, Pos2::new(10., 10.))])
+        );
+        assert_eq!(
+            Some(Pos2::new(20., 10.)),
+            center_after(vec![touch(1, TouchPhase::Start, Pos2::new(30., 10.))])
+        );
+
+        // Only one finger moves, but the center is still between both of them.
+        assert_eq!(
+            Some(Pos2::new(30., 20.)),
+            center_after(vec![touch(1, TouchPhase::Move, Pos2::new(50., 30.))])
+        );
+
+        assert_eq!(
+            None,
+            center_after(vec![touch(0, TouchPhase::End, Pos2::new(10., 10.))])
+        );
+    }
+
+    #[test]
+    fn uv_of_the_ancestor() {
+        let grid = TileGrid::default();
+        let tile_id = TileId {
+            x: 5,
+            y: 2,
+            zoom: 3,
+        };
+
+        // Direct parent is split in four.
+        assert_eq!(
+            Rect::from_min_size(Pos2::new(0.5, 0.), Vec2::splat(0.5)),
+            ancestor_uv(&grid, tile_id, tile_id.parent().unwrap()).unwrap()
+        );
+
+        // Whole world is split into 8 x 8 tiles at zoom 3.
+        assert_eq!(
+            Rect::from_min_size(Pos2::new(5. / 8., 2. / 8.), Vec2::splat(1. / 8.)),
+            ancestor_uv(
+                &grid,
+                tile_id,
+                TileId {
+                    x: 0,
+                    y: 0,
+                    zoom: 0
+                }
+            )
+            .unwrap()
+        );
+
+        // Levels of other grids are not necessarily twice as detailed, so the tiles might
+        // reach beyond their ancestors.
+        let grid = TileGrid::new(
+            std::sync::Arc::new(crate::TransverseMercator::puwg_1992()),
+            geo_types::coord! { x: 0., y: 1000. },
+            100,
+            [1., 0.3]
+                .map(|resolution| crate::Level {
+                    resolution,
+                    matrix_width: 40,
+                    matrix_height: 40,
+                })
+                .to_vec(),
+        );
+        let tile_id = TileId {
+            x: 3,
+            y: 0,
+            zoom: 1,
+        };
+        let uv = ancestor_uv(&grid, tile_id, grid.parent(tile_id).unwrap()).unwrap();
+        approx::assert_abs_diff_eq!(-0.1, uv.min.x, epsilon = 0.0001);
+        approx::assert_abs_diff_eq!(0., uv.min.y, epsilon = 0.0001);
+        approx::assert_abs_diff_eq!(0.3, uv.width(), epsilon = 0.0001);
+        approx::assert_abs_diff_eq!(0.3, uv.height(), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn opacity_is_applied_to_tiles() {
+        let ctx = Context::default();
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        let fetcher = crate::providers::InMemory::default()
+            .with_tile(tile_id, include_bytes!("valid.png").as_slice());
+        let mut tiles = Tiles::with_fetcher(fetcher, Default::default(), ctx.clone());
+
+        let painter = Painter::new(
+            ctx.clone(),
+            egui::LayerId::background(),
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(256.)),
+        );
+        let draw = |tiles: &mut Tiles, opacity| {
+            let resolution = TileGrid::default().resolution(0.);
+            draw_tiles(
+                &painter,
+                geo_types::coord! { x: 0., y: 0. },
+                resolution,
+                0.,
+                tiles,
+                opacity,
+                &ctx,
+            )
+        };
+
+        while draw(&mut tiles, 1.).is_empty() {}
+
+        let color = |shapes: Vec<Shape>| match &shapes[0] {
+            Shape::Mesh(mesh) => mesh.vertices[0].color,
+            _ => panic!("tile should be drawn as a mesh"),
+        };
+        assert_eq!(Color32::WHITE, color(draw(&mut tiles, 1.)));
+        assert_eq!(
+            Color32::from_rgba_premultiplied(128, 128, 128, 128),
+            color(draw(&mut tiles, 0.5))
+        );
+    }
+
+    #[test]
+    fn translucent_ancestor_and_children_do_not_overlap() {
+        let ctx = Context::default();
+        let ancestor = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        let child = TileId {
+            x: 0,
+            y: 0,
+            zoom: 2,
+        };
+        let fetcher = crate::providers::InMemory::default()
+            .with_tile(ancestor, include_bytes!("valid.png").as_slice())
+            .with_tile(child, include_bytes!("valid.png").as_slice());
+        let mut tiles = Tiles::with_fetcher(fetcher, Default::default(), ctx.clone());
+        while tiles.at(ancestor).is_none() || tiles.at(child).is_none() {}
+
+        // Whole world at zoom 1, where no tiles are available.
+        let painter = Painter::new(
+            ctx.clone(),
+            egui::LayerId::background(),
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(512.)),
+        );
+        let mut draw = |opacity| {
+            draw_tiles(
+                &painter,
+                geo_types::coord! { x: 0., y: 0. },
+                TileGrid::default().resolution(1.),
+                0.,
+                &mut tiles,
+                opacity,
+                &ctx,
+            )
+        };
+
+        // Each of the four tiles shows a part of the ancestor, and one of them also the child.
+        assert_eq!(5, draw(1.).len());
+        assert_eq!(4, draw(0.5).len());
+    }
+
+    #[test]
+    fn tiles_are_rotated_around_the_center() {
+        let ctx = Context::default();
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        let fetcher = crate::providers::InMemory::default()
+            .with_tile(tile_id, include_bytes!("valid.png").as_slice());
+        let mut tiles = Tiles::with_fetcher(fetcher, Default::default(), ctx.clone());
+
+        let painter = Painter::new(
+            ctx.clone(),
+            egui::LayerId::background(),
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(256.)),
+        );
+        let shapes = loop {
+            let shapes = draw_tiles(
+                &painter,
+                geo_types::coord! { x: 0., y: 0. },
+                TileGrid::default().resolution(0.),
+                90.,
+                &mut tiles,
+                1.,
+                &ctx,
+            );
+            if !shapes.is_empty() {
+                break shapes;
+            }
+        };
+
+        // Map is facing the east, so the north-west corner of the world is in the bottom-left.
+        let Shape::Mesh(mesh) = &shapes[0] else {
+            panic!("tile should be drawn as a mesh");
+        };
+        assert_eq!(Pos2::new(0., 0.), mesh.vertices[0].uv);
+        approx::assert_abs_diff_eq!(0., mesh.vertices[0].pos.x, epsilon = 0.001);
+        approx::assert_abs_diff_eq!(256., mesh.vertices[0].pos.y, epsilon = 0.001);
+    }
+
+    #[test]
+    fn big_tiles_are_drawn_at_their_natural_size() {
+        struct BigTiles(crate::providers::InMemory);
+
+        impl crate::providers::TileFetcher for BigTiles {
+            fn fetch(&self, tile_id: TileId) -> crate::providers::TileFuture {
+                self.0.fetch(tile_id)
+            }
+
+            fn attributions(&self) -> Vec<Attribution> {
+                Vec::new()
+            }
+
+            fn tile_size(&self) -> u32 {
+                512
             }
         }
+
+        let ctx = Context::default();
+        let tile_id = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        };
+        let fetcher = BigTiles(
+            crate::providers::InMemory::default()
+                .with_tile(tile_id, include_bytes!("valid.png").as_slice()),
+        );
+        let mut tiles = Tiles::with_fetcher(fetcher, Default::default(), ctx.clone());
+
+        // Whole world at zoom 1 is covered by a single tile of level 0.
+        let painter = Painter::new(
+            ctx.clone(),
+            egui::LayerId::background(),
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(512.)),
+        );
+        let shapes = loop {
+            let shapes = draw_tiles(
+                &painter,
+                geo_types::coord! { x: 0., y: 0. },
+                TileGrid::default().resolution(1.),
+                0.,
+                &mut tiles,
+                1.,
+                &ctx,
+            );
+            if !shapes.is_empty() {
+                break shapes;
+            }
+        };
+
+        assert_eq!(1, shapes.len());
+        assert_eq!(
+            Rect::from_min_size(Pos2::ZERO, Vec2::splat(512.)),
+            shapes[0].visual_bounding_rect()
+        );
     }
 }
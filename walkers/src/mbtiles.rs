@@ -0,0 +1,280 @@
+//! Offline tiles stored in an [MBTiles](https://github.com/mapbox/mbtiles-spec) file, which is
+//! an SQLite database.
+
+use std::{path::Path, sync::Mutex};
+
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+use crate::{
+    mercator::TileId,
+    providers::{Attribution, FetchError, TileData, TileFetcher, TileFuture},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MbTilesError {
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("invalid metadata '{0}': {1}")]
+    Metadata(&'static str, String),
+}
+
+/// Contents of the `metadata` table. Only the fields relevant to displaying the tiles are
+/// parsed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub name: Option<String>,
+
+    /// Format of the tile images, e.g. `png` or `jpg`.
+    pub format: Option<String>,
+
+    /// Area covered by the tiles, in degrees.
+    pub bounds: Option<geo_types::Rect<f64>>,
+
+    pub min_zoom: Option<u8>,
+    pub max_zoom: Option<u8>,
+
+    /// Attribution, which might contain HTML.
+    pub attribution: Option<String>,
+}
+
+impl Metadata {
+    fn read(connection: &Connection) -> Result<Self, MbTilesError> {
+        let mut metadata = Self::default();
+        let mut statement = connection.prepare("SELECT name, value FROM metadata")?;
+        let mut rows = statement.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let value: String = row.get(1)?;
+            match name.as_str() {
+                "name" => metadata.name = Some(value),
+                "format" => metadata.format = Some(value),
+                "bounds" => metadata.bounds = Some(parse_bounds(&value)?),
+                "minzoom" => metadata.min_zoom = Some(parse_zoom("minzoom", &value)?),
+                "maxzoom" => metadata.max_zoom = Some(parse_zoom("maxzoom", &value)?),
+                "attribution" => metadata.attribution = Some(value),
+                _ => (),
+            }
+        }
+
+        Ok(metadata)
+    }
+}
+
+fn parse_bounds(value: &str) -> Result<geo_types::Rect<f64>, MbTilesError> {
+    let invalid = || MbTilesError::Metadata("bounds", value.to_owned());
+
+    let coordinates = value
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse::<f64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match coordinates[..] {
+        [west, south, east, north] => Ok(geo_types::Rect::new(
+            geo_types::coord! { x: west, y: south },
+            geo_types::coord! { x: east, y: north },
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_zoom(name: &'static str, value: &str) -> Result<u8, MbTilesError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| MbTilesError::Metadata(name, value.to_owned()))
+}
+
+/// Tile source reading raster tiles from an MBTiles file, without any network access.
+/// Not available on the web.
+///
+/// ```no_run
+/// # use walkers::{MbTiles, Tiles, TilesOptions};
+/// # let egui_ctx = egui::Context::default();
+/// let mbtiles = MbTiles::open("map.mbtiles").unwrap();
+/// let tiles = Tiles::with_fetcher(mbtiles, TilesOptions::default(), egui_ctx);
+/// ```
+pub struct MbTiles {
+    connection: Mutex<Connection>,
+    metadata: Metadata,
+}
+
+impl MbTiles {
+    /// Open the file, in read-only mode, and read its metadata.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MbTilesError> {
+        let connection = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let metadata = Metadata::read(&connection)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+            metadata,
+        })
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Image of the tile, exactly as stored in the file, or `None` if there is no such tile.
+    pub fn tile(&self, tile_id: TileId) -> Result<Option<Vec<u8>>, MbTilesError> {
+        // MBTiles use the TMS scheme, where rows are counted from the south.
+        let Some(row) = tile_id.tms_y() else {
+            return Ok(None);
+        };
+
+        let connection = self
+            .connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let tile = connection
+            .query_row(
+                "SELECT tile_data FROM tiles \
+                WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                (tile_id.zoom, tile_id.x, row),
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(tile)
+    }
+}
+
+impl TileFetcher for MbTiles {
+    /// Tiles are read right away, as it is fast enough to not bother with a separate thread.
+    fn fetch(&self, tile_id: TileId) -> TileFuture {
+        let tile = self
+            .tile(tile_id)
+            .map(|tile| tile.map(TileData::Bytes))
+            .map_err(FetchError::from);
+        Box::pin(futures::future::ready(tile))
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        self.metadata
+            .attribution
+            .iter()
+            .map(|attribution| Attribution::new(attribution, ""))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Create an MBTiles file with a single, valid tile at `3/1/2`.
+    pub(crate) fn create(path: &Path) {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE metadata (name TEXT, value TEXT);
+                CREATE TABLE tiles (
+                    zoom_level INTEGER,
+                    tile_column INTEGER,
+                    tile_row INTEGER,
+                    tile_data BLOB
+                );
+                INSERT INTO metadata VALUES
+                    ('name', 'Test'),
+                    ('format', 'png'),
+                    ('bounds', '-180.0,-85,180,85'),
+                    ('minzoom', '0'),
+                    ('maxzoom', '3'),
+                    ('attribution', 'Test contributors');",
+            )
+            .unwrap();
+
+        // Row 5 in TMS is row 2 in XYZ, for zoom 3.
+        connection
+            .execute(
+                "INSERT INTO tiles VALUES (3, 1, 5, ?1)",
+                [include_bytes!("valid.png").as_slice()],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn metadata_is_read() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("test.mbtiles");
+        create(&path);
+
+        let mbtiles = MbTiles::open(&path).unwrap();
+        assert_eq!(
+            &Metadata {
+                name: Some("Test".to_owned()),
+                format: Some("png".to_owned()),
+                bounds: Some(geo_types::Rect::new(
+                    geo_types::coord! { x: -180., y: -85. },
+                    geo_types::coord! { x: 180., y: 85. },
+                )),
+                min_zoom: Some(0),
+                max_zoom: Some(3),
+                attribution: Some("Test contributors".to_owned()),
+            },
+            mbtiles.metadata()
+        );
+        assert_eq!(
+            vec![Attribution::new("Test contributors", "")],
+            mbtiles.attributions()
+        );
+    }
+
+    #[test]
+    fn tile_rows_are_flipped() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("test.mbtiles");
+        create(&path);
+
+        let mbtiles = MbTiles::open(&path).unwrap();
+        assert_eq!(
+            Some(include_bytes!("valid.png").to_vec()),
+            mbtiles
+                .tile(TileId {
+                    x: 1,
+                    y: 2,
+                    zoom: 3
+                })
+                .unwrap()
+        );
+        assert_eq!(
+            None,
+            mbtiles
+                .tile(TileId {
+                    x: 1,
+                    y: 5,
+                    zoom: 3
+                })
+                .unwrap()
+        );
+
+        // Out of the world.
+        assert_eq!(
+            None,
+            mbtiles
+                .tile(TileId {
+                    x: 1,
+                    y: 8,
+                    zoom: 3
+                })
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_metadata_is_reported() {
+        assert!(matches!(
+            parse_bounds("1,2,3"),
+            Err(MbTilesError::Metadata("bounds", _))
+        ));
+        assert!(matches!(
+            parse_zoom("maxzoom", "high"),
+            Err(MbTilesError::Metadata("maxzoom", _))
+        ));
+    }
+}
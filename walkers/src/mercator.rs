@@ -18,15 +18,26 @@ use egui::Pos2;
 use std::f64::consts::PI;
 
 pub trait PositionExt {
-    /// Project geographical position into a 2D plane using Mercator.
-    fn project(&self, zoom: u8) -> Pixels;
+    /// Project geographical position into a 2D plane using Mercator. Zoom can be fractional, in
+    /// which case the plane is scaled accordingly.
+    fn project(&self, zoom: f64) -> Pixels;
 
     /// Tile this position is on.
     fn tile_id(&self, zoom: u8) -> TileId;
 }
 
-/// Size of the tiles used by the services like the OSM.
-const TILE_SIZE: u32 = 256;
+/// Size of the tiles used by the services like the OSM. Zoom levels, and the "World bitmap",
+/// are defined by these, regardless of the tile size of the actual source.
+pub(crate) const TILE_SIZE: u32 = 256;
+
+/// Half of the Earth's circumference, i.e. how far the Web Mercator (EPSG:3857) coordinates
+/// reach in each direction, in meters.
+pub(crate) const WEB_MERCATOR_EXTENT: f64 = 20_037_508.342_789_244;
+
+/// Size of the "World bitmap" at given, possibly fractional, zoom.
+fn number_of_pixels(zoom: f64) -> f64 {
+    2f64.powf(zoom) * TILE_SIZE as f64
+}
 
 fn mercator_normalized((x, y): (f64, f64)) -> (f64, f64) {
     // Project into Mercator (cylindrical map projection).
@@ -41,13 +52,13 @@ fn mercator_normalized((x, y): (f64, f64)) -> (f64, f64) {
 }
 
 impl PositionExt for Position {
-    fn project(&self, zoom: u8) -> Pixels {
+    fn project(&self, zoom: f64) -> Pixels {
         let (x, y) = mercator_normalized((*self).into());
 
         // Map that into a big bitmap made out of web tiles.
-        let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
-        let x = x * number_of_pixels as f64;
-        let y = y * number_of_pixels as f64;
+        let number_of_pixels = number_of_pixels(zoom);
+        let x = x * number_of_pixels;
+        let y = y * number_of_pixels;
 
         Pixels::new(x as f32, y as f32)
     }
@@ -84,9 +95,57 @@ impl TileId {
         Pixels::new((self.x * TILE_SIZE) as f32, (self.y * TILE_SIZE) as f32)
     }
 
+    /// Tile of the previous zoom level, which covers this one.
+    pub fn parent(&self) -> Option<TileId> {
+        Some(TileId {
+            x: self.x / 2,
+            y: self.y / 2,
+            zoom: self.zoom.checked_sub(1)?,
+        })
+    }
+
+    /// Four tiles of the next zoom level, which cover this one.
+    pub fn children(&self) -> [TileId; 4] {
+        let zoom = self.zoom + 1;
+        let (x, y) = (self.x * 2, self.y * 2);
+        [
+            TileId { x, y, zoom },
+            TileId { x: x + 1, y, zoom },
+            TileId { x, y: y + 1, zoom },
+            TileId {
+                x: x + 1,
+                y: y + 1,
+                zoom,
+            },
+        ]
+    }
+
+    /// Row of the tile in the TMS scheme, where rows are counted from the south, as opposed to
+    /// the XYZ scheme used by [`TileId`]. `None` if the tile is out of the world.
+    pub fn tms_y(&self) -> Option<u32> {
+        self.tiles_per_side().checked_sub(self.y.checked_add(1)?)
+    }
+
+    /// Area covered by the tile, in Web Mercator (EPSG:3857) meters.
+    pub fn bounding_box(&self) -> geo_types::Rect {
+        let size = 2. * WEB_MERCATOR_EXTENT / self.tiles_per_side() as f64;
+        let west = self.x as f64 * size - WEB_MERCATOR_EXTENT;
+        let north = WEB_MERCATOR_EXTENT - self.y as f64 * size;
+
+        geo_types::Rect::new(
+            geo_types::coord! { x: west, y: north - size },
+            geo_types::coord! { x: west + size, y: north },
+        )
+    }
+
+    /// Number of tiles in each row and column at this tile's zoom level.
+    fn tiles_per_side(&self) -> u32 {
+        2u32.pow(self.zoom as u32)
+    }
+
     pub fn east(&self) -> Option<TileId> {
         Some(TileId {
-            x: self.x + 1,
+            x: (self.x + 1 < self.tiles_per_side()).then_some(self.x + 1)?,
             y: self.y,
             zoom: self.zoom,
         })
@@ -111,16 +170,15 @@ impl TileId {
     pub fn south(&self) -> Option<TileId> {
         Some(TileId {
             x: self.x,
-            y: self.y + 1,
+            y: (self.y + 1 < self.tiles_per_side()).then_some(self.y + 1)?,
             zoom: self.zoom,
         })
     }
 }
 
-/// Transforms screen pixels into a geographical position.
-pub fn screen_to_position(pixels: Pixels, zoom: u8) -> Position {
-    let number_of_pixels = 2u32.pow(zoom as u32) * TILE_SIZE;
-    let number_of_pixels: f64 = number_of_pixels.into();
+/// Transforms screen pixels into a geographical position. Zoom can be fractional.
+pub fn screen_to_position(pixels: Pixels, zoom: f64) -> Position {
+    let number_of_pixels = number_of_pixels(zoom);
 
     let lon = pixels.x as f64;
     let lon = lon / number_of_pixels;
@@ -164,14 +222,133 @@ mod tests {
         // position on the tile.
         assert_eq!(
             Pixels::new(36590. * 256. + 252., 21569. * 256. + 7.5),
-            citadel.project(zoom)
+            citadel.project(zoom as f64)
         );
     }
 
+    #[test]
+    fn projecting_at_fractional_zoom() {
+        let citadel = Position::new(21.00027, 52.26470);
+
+        // Half a zoom level further, everything is scaled by the square root of two.
+        let projected = citadel.project(16.5);
+        let expected = citadel.project(16.).to_vec2() * 2f32.sqrt();
+        approx::assert_relative_eq!(projected.x, expected.x, max_relative = 0.0001);
+        approx::assert_relative_eq!(projected.y, expected.y, max_relative = 0.0001);
+    }
+
+    #[test]
+    fn tile_neighbours_stay_within_the_world() {
+        let corner = TileId {
+            x: 1,
+            y: 1,
+            zoom: 1,
+        };
+
+        assert_eq!(None, corner.east());
+        assert_eq!(None, corner.south());
+        assert_eq!(Some(TileId { x: 0, ..corner }), corner.west());
+        assert_eq!(Some(TileId { y: 0, ..corner }), corner.north());
+    }
+
+    #[test]
+    fn tile_parent_and_children() {
+        let tile_id = TileId {
+            x: 5,
+            y: 2,
+            zoom: 3,
+        };
+
+        assert_eq!(
+            Some(TileId {
+                x: 2,
+                y: 1,
+                zoom: 2
+            }),
+            tile_id.parent()
+        );
+        assert_eq!(
+            None,
+            TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            }
+            .parent()
+        );
+
+        for child in tile_id.children() {
+            assert_eq!(Some(tile_id), child.parent());
+        }
+    }
+
+    #[test]
+    fn tms_rows_are_counted_from_the_south() {
+        assert_eq!(
+            Some(0),
+            TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            }
+            .tms_y()
+        );
+        assert_eq!(
+            Some(5),
+            TileId {
+                x: 1,
+                y: 2,
+                zoom: 3
+            }
+            .tms_y()
+        );
+        assert_eq!(
+            None,
+            TileId {
+                x: 1,
+                y: 8,
+                zoom: 3
+            }
+            .tms_y()
+        );
+    }
+
+    #[test]
+    fn tile_bounding_box_in_web_mercator() {
+        let world = TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        }
+        .bounding_box();
+        assert_eq!(-WEB_MERCATOR_EXTENT, world.min().x);
+        assert_eq!(-WEB_MERCATOR_EXTENT, world.min().y);
+        assert_eq!(WEB_MERCATOR_EXTENT, world.max().x);
+        assert_eq!(WEB_MERCATOR_EXTENT, world.max().y);
+
+        // North-east quarter of the world.
+        let tile = TileId {
+            x: 1,
+            y: 0,
+            zoom: 1,
+        }
+        .bounding_box();
+        assert_eq!(0., tile.min().x);
+        assert_eq!(0., tile.min().y);
+        assert_eq!(WEB_MERCATOR_EXTENT, tile.max().x);
+        assert_eq!(WEB_MERCATOR_EXTENT, tile.max().y);
+    }
+
     #[test]
     fn project_there_and_back() {
         let citadel = Position::new(21.00027, 52.26470);
-        let zoom = 16;
+        let zoom = 16.;
+        let calculated = screen_to_position(citadel.project(zoom), zoom);
+
+        approx::assert_relative_eq!(calculated.x(), citadel.x(), max_relative = 1.0);
+        approx::assert_relative_eq!(calculated.y(), citadel.y(), max_relative = 1.0);
+
+        let zoom = 16.3;
         let calculated = screen_to_position(citadel.project(zoom), zoom);
 
         approx::assert_relative_eq!(calculated.x(), citadel.x(), max_relative = 1.0);
@@ -1,16 +1,495 @@
 //! Some common tile map providers.
 
-use crate::mercator::TileId;
+use std::{collections::HashMap, sync::Arc};
 
-#[derive(Clone, Copy)]
+use egui_extras::RetainedImage;
+
+use crate::{grid::TileGrid, mercator::TileId};
+
+pub mod wms;
+pub mod wmts;
+
+/// Single entry of the tile source's attribution. Sources might have several of them, e.g. one
+/// for the imagery, and one for the underlying data.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Attribution {
-    pub text: &'static str,
-    pub url: &'static str,
+    pub text: String,
+
+    /// Link opened when the text is clicked. Empty if there is none.
+    pub url: String,
+
+    /// Image shown before the text.
+    pub logo: Option<Logo>,
+
+    /// Whether provider's terms of use require it to be always visible. Other entries are
+    /// collapsed by the [`crate::Map`]'s overlay until the user expands them.
+    pub required: bool,
+}
+
+impl Attribution {
+    /// Required attribution, without the logo.
+    pub fn new(text: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            url: url.into(),
+            logo: None,
+            required: true,
+        }
+    }
+
+    pub fn with_logo(mut self, logo: Logo) -> Self {
+        self.logo = Some(logo);
+        self
+    }
+
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
 }
 
+/// Logo shown next to the [`Attribution`].
+#[derive(Clone)]
+pub struct Logo {
+    image: Arc<RetainedImage>,
+}
+
+impl Logo {
+    /// Decode the image, e.g. PNG.
+    pub fn from_image_bytes(debug_name: impl Into<String>, image: &[u8]) -> Result<Self, String> {
+        RetainedImage::from_image_bytes(debug_name, image).map(|image| Self {
+            image: Arc::new(image),
+        })
+    }
+
+    pub fn from_color_image(debug_name: impl Into<String>, image: egui::ColorImage) -> Self {
+        Self {
+            image: Arc::new(RetainedImage::from_color_image(debug_name, image)),
+        }
+    }
+
+    /// Show the logo, scaled to the given height.
+    pub(crate) fn show(&self, ui: &mut egui::Ui, height: f32) -> egui::Response {
+        let size = self.image.size_vec2();
+        let size = size * (height / size.y.max(1.));
+        self.image.show_size(ui, size)
+    }
+}
+
+impl std::fmt::Debug for Logo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Logo")
+            .field("size", &self.image.size())
+            .finish()
+    }
+}
+
+/// Logos are equal only if they are the same image.
+impl PartialEq for Logo {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.image, &other.image)
+    }
+}
+
+impl Eq for Logo {}
+
 pub trait TileSource {
     fn tile_url(&self, tile_id: TileId) -> String;
     fn attribution(&self) -> Attribution;
+
+    /// All attribution entries of the source, e.g. one for the imagery, and one for the
+    /// underlying data. Default is just the [`TileSource::attribution`].
+    fn attributions(&self) -> Vec<Attribution> {
+        vec![self.attribution()]
+    }
+
+    /// Size (in pixels) of the tiles' images, at scale 1. Zoom levels are defined by 256 pixel
+    /// tiles, so bigger ones are taken from lower levels, e.g. 512 pixel tiles of level 4 are
+    /// drawn at zoom 5.
+    fn tile_size(&self) -> u32 {
+        256
+    }
+
+    /// Layout of the tiles. Default is the Web Mercator grid of [`TileSource::tile_size`] tiles,
+    /// used by most of the tile servers. Tiles in other CRSs can be drawn only on a map using
+    /// the same CRS, see [`crate::Map::with_grid`].
+    fn grid(&self) -> TileGrid {
+        TileGrid::web_mercator(self.tile_size())
+    }
+
+    /// Highest scale factor of the tiles this source provides, e.g. 2 if it has "@2x" tiles
+    /// for HiDPI screens. [`crate::Map`] picks the scale matching the screen's pixels per point.
+    fn max_scale(&self) -> u32 {
+        1
+    }
+
+    /// URL of the tile at given scale factor, between 1 and [`TileSource::max_scale`]. Its
+    /// image should be `scale` times bigger than the regular one, as it is drawn in the same
+    /// area of the screen.
+    fn scaled_tile_url(&self, tile_id: TileId, scale: u32) -> String {
+        let _ = scale;
+        self.tile_url(tile_id)
+    }
+}
+
+/// Image of a tile, returned by a [`TileFetcher`].
+pub enum TileData {
+    /// Encoded image, e.g. PNG or JPEG.
+    Bytes(Vec<u8>),
+
+    /// Already decoded image, e.g. generated procedurally.
+    Image(egui::ColorImage),
+}
+
+/// Reason why a [`TileFetcher`] could not get the tile.
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error("invalid HTTP client configuration: {0}")]
+    Client(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[cfg(all(feature = "mbtiles", not(target_family = "wasm")))]
+    #[error(transparent)]
+    MbTiles(#[from] crate::MbTilesError),
+
+    /// Error of a custom fetcher.
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Result of [`TileFetcher::fetch`]. `None` means that there is no such tile.
+pub type FetchResult = Result<Option<TileData>, FetchError>;
+
+/// Future returned by [`TileFetcher::fetch`]. It must not borrow the fetcher, so it typically
+/// holds `Arc`s of whatever it needs.
+#[cfg(not(target_family = "wasm"))]
+pub type TileFuture = futures::future::BoxFuture<'static, FetchResult>;
+
+/// Future returned by [`TileFetcher::fetch`]. It must not borrow the fetcher, so it typically
+/// holds `Arc`s of whatever it needs.
+#[cfg(target_family = "wasm")]
+pub type TileFuture = futures::future::LocalBoxFuture<'static, FetchResult>;
+
+/// Lower level source of the tiles, which gets their images by any means, such as reading files
+/// or a database, or generating them. Only [`FetchError::Http`] failures are retried, if they are
+/// transient (see [`crate::Retry`]).
+///
+/// It is used by [`crate::Tiles::with_fetcher`], and called from the IO thread.
+pub trait TileFetcher {
+    fn fetch(&self, tile_id: TileId) -> TileFuture;
+    fn attributions(&self) -> Vec<Attribution>;
+
+    /// Size (in pixels) of the tiles' images, see [`TileSource::tile_size`].
+    fn tile_size(&self) -> u32 {
+        256
+    }
+
+    /// Layout of the tiles, see [`TileSource::grid`].
+    fn grid(&self) -> TileGrid {
+        TileGrid::web_mercator(self.tile_size())
+    }
+}
+
+/// Tiles kept in memory, e.g. bundled with the application or prepared for tests.
+#[derive(Clone, Default)]
+pub struct InMemory {
+    tiles: HashMap<TileId, Arc<[u8]>>,
+}
+
+impl InMemory {
+    /// Add the tile's encoded image, e.g. PNG or JPEG.
+    pub fn insert(&mut self, tile_id: TileId, image: impl Into<Arc<[u8]>>) {
+        self.tiles.insert(tile_id, image.into());
+    }
+
+    pub fn with_tile(mut self, tile_id: TileId, image: impl Into<Arc<[u8]>>) -> Self {
+        self.insert(tile_id, image);
+        self
+    }
+}
+
+impl TileFetcher for InMemory {
+    fn fetch(&self, tile_id: TileId) -> TileFuture {
+        let image = self
+            .tiles
+            .get(&tile_id)
+            .map(|image| TileData::Bytes(image.to_vec()));
+        Box::pin(futures::future::ready(Ok(image)))
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        Vec::new()
+    }
+}
+
+/// Order of the tile rows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scheme {
+    /// Rows are counted from the north, as in OpenStreetMap and [`TileId`].
+    #[default]
+    Xyz,
+
+    /// Rows are counted from the south, as in gdal2tiles' default output.
+    Tms,
+}
+
+/// Tiles read from a directory, such as the output of gdal2tiles, without any network access.
+/// Not available on the web.
+///
+/// ```
+/// # use walkers::providers::{Directory, Scheme};
+/// let directory = Directory::new("tiles")
+///     .with_template("{z}/{x}/{y}.{ext}")
+///     .with_extension("jpg")
+///     .with_scheme(Scheme::Tms);
+/// ```
+#[cfg(not(target_family = "wasm"))]
+#[derive(Clone, Debug)]
+pub struct Directory {
+    root: std::path::PathBuf,
+    template: String,
+    extension: String,
+    scheme: Scheme,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl Directory {
+    /// Tiles stored in `{z}/{x}/{y}.png` files inside the given directory.
+    pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            template: "{z}/{x}/{y}.{ext}".to_owned(),
+            extension: "png".to_owned(),
+            scheme: Scheme::Xyz,
+        }
+    }
+
+    /// Path of the tiles, relative to the root directory. `{z}`, `{x}`, `{y}` and `{ext}` are
+    /// replaced by the zoom, column, row and the extension.
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    /// Extension of the tile files, `png` by default.
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        self.extension = extension.into();
+        self
+    }
+
+    pub fn with_scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Path of the tile's file, or `None` if the tile is out of the world.
+    pub fn path(&self, tile_id: TileId) -> Option<std::path::PathBuf> {
+        let y = match self.scheme {
+            Scheme::Xyz => tile_id.y,
+            Scheme::Tms => tile_id.tms_y()?,
+        };
+
+        let path = self
+            .template
+            .replace("{z}", &tile_id.zoom.to_string())
+            .replace("{x}", &tile_id.x.to_string())
+            .replace("{y}", &y.to_string())
+            .replace("{ext}", &self.extension);
+
+        Some(self.root.join(path))
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl TileFetcher for Directory {
+    /// Files are read right away, as it is fast enough to not bother with a separate thread.
+    fn fetch(&self, tile_id: TileId) -> TileFuture {
+        let image = match self.path(tile_id).map(std::fs::read) {
+            None => Ok(None),
+            Some(Ok(image)) => Ok(Some(TileData::Bytes(image))),
+            Some(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Some(Err(e)) => Err(e.into()),
+        };
+        Box::pin(futures::future::ready(image))
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        Vec::new()
+    }
+}
+
+/// Tile source defined by a URL template, so tile servers can be configured at runtime, e.g.
+/// from a config file.
+///
+/// Supported placeholders are:
+///  * `{z}`, `{x}`, `{y}` - zoom, column and row of the tile,
+///  * `{-y}` - row counted from the south, as in TMS,
+///  * `{q}` - quadkey, as used by Bing Maps,
+///  * `{s}` - one of the subdomains (`a`, `b` or `c` by default),
+///  * `{r}` - `@2x` when high resolution tiles are requested (see
+///    [`TemplateSource::with_retina`]), empty otherwise,
+///  * any other ones, added with [`TemplateSource::with_placeholder`].
+///
+/// ```
+/// # use walkers::providers::{Attribution, TemplateSource};
+/// let source = TemplateSource::new(
+///     "https://{s}.tile.example.com/{z}/{x}/{y}{r}.png?key={apikey}",
+///     Attribution::new("Example", "https://example.com"),
+/// )
+/// .with_placeholder("apikey", "secret")
+/// .with_retina(true);
+/// ```
+#[derive(Clone, Debug)]
+pub struct TemplateSource {
+    template: String,
+    subdomains: Vec<String>,
+    tile_size: u32,
+    retina: bool,
+    placeholders: HashMap<String, String>,
+    attributions: Vec<Attribution>,
+}
+
+impl TemplateSource {
+    pub fn new(template: impl Into<String>, attribution: Attribution) -> Self {
+        Self {
+            template: template.into(),
+            subdomains: ["a", "b", "c"].map(str::to_owned).to_vec(),
+            tile_size: 256,
+            retina: false,
+            placeholders: HashMap::new(),
+            attributions: vec![attribution],
+        }
+    }
+
+    /// Additional attribution entry.
+    pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+        self.attributions.push(attribution);
+        self
+    }
+
+    /// Subdomains substituted for `{s}`. Each tile always uses the same one, so they are cached
+    /// properly by the browsers and proxies.
+    pub fn with_subdomains(
+        mut self,
+        subdomains: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.subdomains = subdomains.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Size (in pixels) of the server's tiles, e.g. 512. Default is 256.
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Whether the server has high resolution ("@2x") tiles. If so, they are requested through
+    /// `{r}` on HiDPI screens.
+    pub fn with_retina(mut self, retina: bool) -> Self {
+        self.retina = retina;
+        self
+    }
+
+    /// Custom placeholder, such as an API key. `name` is given without the braces.
+    pub fn with_placeholder(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.placeholders.insert(name.into(), value.into());
+        self
+    }
+
+    fn value(&self, name: &str, tile_id: TileId, scale: u32) -> Option<String> {
+        match name {
+            "z" => Some(tile_id.zoom.to_string()),
+            "x" => Some(tile_id.x.to_string()),
+            "y" => Some(tile_id.y.to_string()),
+            "-y" => tile_id.tms_y().map(|y| y.to_string()),
+            "q" => Some(quadkey(tile_id)),
+            "s" if !self.subdomains.is_empty() => {
+                let index = (tile_id.x as usize + tile_id.y as usize) % self.subdomains.len();
+                Some(self.subdomains[index].clone())
+            }
+            "r" if scale > 1 => Some(format!("@{}x", scale)),
+            "r" => Some(String::new()),
+            _ => self.placeholders.get(name).cloned(),
+        }
+    }
+}
+
+impl TileSource for TemplateSource {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        self.scaled_tile_url(tile_id, 1)
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    fn max_scale(&self) -> u32 {
+        if self.retina {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn scaled_tile_url(&self, tile_id: TileId, scale: u32) -> String {
+        let mut url = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(start) = rest.find('{') {
+            url.push_str(&rest[..start]);
+            let placeholder = &rest[start..];
+
+            // Unclosed brace is left as it is, together with the rest of the template.
+            let Some(end) = placeholder.find('}') else {
+                rest = placeholder;
+                break;
+            };
+
+            // Unknown placeholders are left as they are.
+            match self.value(&placeholder[1..end], tile_id, scale) {
+                Some(value) => url.push_str(&value),
+                None => url.push_str(&placeholder[..=end]),
+            }
+            rest = &placeholder[end + 1..];
+        }
+
+        url.push_str(rest);
+        url
+    }
+
+    fn attribution(&self) -> Attribution {
+        first_attribution(&self.attributions)
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        self.attributions.clone()
+    }
+}
+
+/// Main entry of the source having several of them, for [`TileSource::attribution`].
+pub(crate) fn first_attribution(attributions: &[Attribution]) -> Attribution {
+    attributions
+        .first()
+        .cloned()
+        .unwrap_or_else(|| Attribution::new("", ""))
+}
+
+/// Tile's quadkey, as described in
+/// <https://learn.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system>.
+fn quadkey(tile_id: TileId) -> String {
+    (1..=tile_id.zoom)
+        .rev()
+        .map(|level| {
+            let mask = 1 << (level - 1);
+            let digit = u8::from(tile_id.x & mask != 0) + 2 * u8::from(tile_id.y & mask != 0);
+            char::from(b'0' + digit)
+        })
+        .collect()
 }
 
 /// <https://www.openstreetmap.org/about>
@@ -25,10 +504,10 @@ impl TileSource for OpenStreetMap {
     }
 
     fn attribution(&self) -> Attribution {
-        Attribution {
-            text: "OpenStreetMap contributors",
-            url: "https://www.openstreetmap.org/copyright",
-        }
+        Attribution::new(
+            "OpenStreetMap contributors",
+            "https://www.openstreetmap.org/copyright",
+        )
     }
 }
 
@@ -53,9 +532,171 @@ impl TileSource for Geoportal {
     }
 
     fn attribution(&self) -> Attribution {
-        Attribution {
-            text: "Główny Urząd Geodezji i Kartografii",
-            url: "https://www.geoportal.gov.pl/",
-        }
+        Attribution::new(
+            "Główny Urząd Geodezji i Kartografii",
+            "https://www.geoportal.gov.pl/",
+        )
+    }
+}
+
+/// Characters which need to be escaped in a value of the query parameter. Slashes and colons
+/// are allowed there, and they are common in formats and CRS identifiers.
+const QUERY_VALUE: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/')
+    .remove(b':');
+
+/// Escape the value of a query parameter, e.g. a layer name, which might contain `&` or spaces.
+fn query_value(value: &str) -> String {
+    percent_encoding::utf8_percent_encode(value, QUERY_VALUE).to_string()
+}
+
+/// What to put between the URL and the additional query parameters.
+fn query_separator(url: &str) -> &'static str {
+    if url.ends_with('?') || url.ends_with('&') {
+        ""
+    } else if url.contains('?') {
+        "&"
+    } else {
+        "?"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TILE_ID: TileId = TileId {
+        x: 1,
+        y: 2,
+        zoom: 3,
+    };
+
+    #[test]
+    fn template_placeholders() {
+        let source = TemplateSource::new(
+            "https://{s}.example.com/{z}/{x}/{y}{r}.png?tms={-y}&q={q}&key={apikey}&{unknown}",
+            Attribution::new("Example", "https://example.com"),
+        )
+        .with_placeholder("apikey", "secret");
+
+        assert_eq!(
+            "https://a.example.com/3/1/2.png?tms=5&q=021&key=secret&{unknown}",
+            source.tile_url(TILE_ID)
+        );
+        assert_eq!(
+            vec![Attribution::new("Example", "https://example.com")],
+            source.attributions()
+        );
+
+        // High resolution tiles are requested only when asked for.
+        assert_eq!(1, source.max_scale());
+        let source = source.with_retina(true);
+        assert_eq!(2, source.max_scale());
+        assert!(source.tile_url(TILE_ID).contains("/3/1/2.png"));
+        assert!(source.scaled_tile_url(TILE_ID, 2).contains("/3/1/2@2x.png"));
+    }
+
+    #[test]
+    fn sources_have_single_attribution_by_default() {
+        assert_eq!(
+            vec![OpenStreetMap.attribution()],
+            OpenStreetMap.attributions()
+        );
+    }
+
+    #[test]
+    fn template_with_unclosed_placeholder() {
+        let source = TemplateSource::new("https://x/{z}/{x", Attribution::new("", ""));
+        assert_eq!("https://x/3/{x", source.tile_url(TILE_ID));
+    }
+
+    #[test]
+    fn template_subdomains_are_rotated() {
+        let source =
+            TemplateSource::new("{s}", Attribution::new("", "")).with_subdomains(["1", "2"]);
+        assert_eq!(
+            "1",
+            source.tile_url(TileId {
+                x: 0,
+                y: 0,
+                zoom: 1
+            })
+        );
+        assert_eq!(
+            "2",
+            source.tile_url(TileId {
+                x: 1,
+                y: 0,
+                zoom: 1
+            })
+        );
+        assert_eq!(
+            "1",
+            source.tile_url(TileId {
+                x: 1,
+                y: 1,
+                zoom: 1
+            })
+        );
+    }
+
+    #[test]
+    fn quadkeys() {
+        assert_eq!(
+            "",
+            quadkey(TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            })
+        );
+        assert_eq!(
+            "213",
+            quadkey(TileId {
+                x: 3,
+                y: 5,
+                zoom: 3
+            })
+        );
+        assert_eq!("021", quadkey(TILE_ID));
+    }
+
+    #[test]
+    fn directory_paths() {
+        let directory = Directory::new("/tiles");
+        assert_eq!(
+            Some(std::path::PathBuf::from("/tiles/3/1/2.png")),
+            directory.path(TILE_ID)
+        );
+
+        let directory = Directory::new("/tiles")
+            .with_template("z{z}/{y}_{x}.{ext}")
+            .with_extension("jpg")
+            .with_scheme(Scheme::Tms);
+        assert_eq!(
+            Some(std::path::PathBuf::from("/tiles/z3/5_1.jpg")),
+            directory.path(TILE_ID)
+        );
+        assert_eq!(None, directory.path(TileId { y: 8, ..TILE_ID }));
+    }
+
+    #[test]
+    fn tiles_are_read_from_directory() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("3/1")).unwrap();
+        std::fs::write(root.path().join("3/1/2.png"), b"image").unwrap();
+
+        let directory = Directory::new(root.path());
+        let fetch = |tile_id| futures::executor::block_on(directory.fetch(tile_id));
+
+        assert!(matches!(
+            fetch(TILE_ID),
+            Ok(Some(TileData::Bytes(image))) if image == b"image"
+        ));
+        assert!(matches!(fetch(TileId { x: 2, ..TILE_ID }), Ok(None)));
     }
 }
@@ -0,0 +1,275 @@
+//! Tiles rendered by any [WMS](https://www.ogc.org/standard/wms/) server, requested one by one
+//! with GetMap requests in the Web Mercator (EPSG:3857).
+//!
+//! ```
+//! # use walkers::providers::{Attribution, wms::Wms};
+//! let source = Wms::new(
+//!     "https://maps.example.com/wms",
+//!     ["roads", "buildings"],
+//!     Attribution::new("Example Mapping Agency", "https://maps.example.com/"),
+//! )
+//! .with_transparent(true);
+//! ```
+
+use crate::{
+    mercator::TileId,
+    providers::{query_separator, query_value, Attribution, TileSource},
+};
+
+/// Version of the WMS protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Version {
+    V1_1_1,
+    #[default]
+    V1_3_0,
+}
+
+impl Version {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Version::V1_1_1 => "1.1.1",
+            Version::V1_3_0 => "1.3.0",
+        }
+    }
+
+    /// Name of the parameter holding the coordinate system, which was renamed in 1.3.0.
+    fn crs_parameter(&self) -> &'static str {
+        match self {
+            Version::V1_1_1 => "SRS",
+            Version::V1_3_0 => "CRS",
+        }
+    }
+}
+
+/// GetMap requests for the area of each tile.
+#[derive(Clone, Debug)]
+pub struct Wms {
+    url: String,
+    version: Version,
+    layers: Vec<String>,
+    styles: Vec<String>,
+    format: String,
+    transparent: bool,
+    tile_size: u32,
+    attributions: Vec<Attribution>,
+}
+
+impl Wms {
+    /// Layers of the service at `url`, drawn in the given order, using the default styles.
+    pub fn new(
+        url: impl Into<String>,
+        layers: impl IntoIterator<Item = impl Into<String>>,
+        attribution: Attribution,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            version: Version::default(),
+            layers: layers.into_iter().map(Into::into).collect(),
+            styles: Vec::new(),
+            format: "image/png".to_owned(),
+            transparent: false,
+            tile_size: 256,
+            attributions: vec![attribution],
+        }
+    }
+
+    /// Additional attribution entry.
+    pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+        self.attributions.push(attribution);
+        self
+    }
+
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Styles, one for each layer. Empty string stands for the layer's default style.
+    pub fn with_styles(mut self, styles: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.styles = styles.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Format (MIME type) of the images, e.g. `image/jpeg`.
+    pub fn with_format(mut self, format: impl Into<String>) -> Self {
+        self.format = format.into();
+        self
+    }
+
+    /// Size (in pixels) of the requested images. Bigger tiles mean fewer requests, and
+    /// labels cut at tiles' edges less often. Default is 256.
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    /// Whether areas without data should be transparent, so that layers below are visible.
+    pub fn with_transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+}
+
+impl TileSource for Wms {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        self.scaled_tile_url(tile_id, 1)
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Server renders the images at any size, so they can be as sharp as the screen needs.
+    fn max_scale(&self) -> u32 {
+        4
+    }
+
+    fn scaled_tile_url(&self, tile_id: TileId, scale: u32) -> String {
+        let size = self.tile_size * scale;
+
+        // EPSG:3857 uses easting, northing axis order in both versions.
+        let bounding_box = tile_id.bounding_box();
+
+        format!(
+            "{}{}SERVICE=WMS&REQUEST=GetMap&VERSION={}&LAYERS={}&STYLES={}&FORMAT={}\
+            &TRANSPARENT={}&{}=EPSG:3857&BBOX={},{},{},{}&WIDTH={}&HEIGHT={}",
+            self.url,
+            query_separator(&self.url),
+            self.version.as_str(),
+            // Commas separate the values, so they must stay as they are.
+            join_query_values(&self.layers),
+            join_query_values(&self.styles),
+            query_value(&self.format),
+            if self.transparent { "TRUE" } else { "FALSE" },
+            self.version.crs_parameter(),
+            bounding_box.min().x,
+            bounding_box.min().y,
+            bounding_box.max().x,
+            bounding_box.max().y,
+            size,
+            size
+        )
+    }
+
+    fn attribution(&self) -> Attribution {
+        super::first_attribution(&self.attributions)
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        self.attributions.clone()
+    }
+}
+
+fn join_query_values(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| query_value(value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> Wms {
+        Wms::new(
+            "https://maps.example.com/wms",
+            ["roads", "buildings"],
+            Attribution::new("Example", "https://example.com/"),
+        )
+    }
+
+    #[test]
+    fn get_map_urls() {
+        let tile_id = TileId {
+            x: 1,
+            y: 0,
+            zoom: 1,
+        };
+
+        assert_eq!(
+            "https://maps.example.com/wms?SERVICE=WMS&REQUEST=GetMap&VERSION=1.3.0\
+            &LAYERS=roads,buildings&STYLES=&FORMAT=image/png&TRANSPARENT=FALSE&CRS=EPSG:3857\
+            &BBOX=0,0,20037508.342789244,20037508.342789244&WIDTH=256&HEIGHT=256",
+            source().tile_url(tile_id)
+        );
+
+        assert_eq!(
+            "https://maps.example.com/wms?SERVICE=WMS&REQUEST=GetMap&VERSION=1.1.1\
+            &LAYERS=roads,buildings&STYLES=,dark&FORMAT=image/jpeg&TRANSPARENT=TRUE&SRS=EPSG:3857\
+            &BBOX=0,0,20037508.342789244,20037508.342789244&WIDTH=256&HEIGHT=256",
+            source()
+                .with_version(Version::V1_1_1)
+                .with_styles(["", "dark"])
+                .with_format("image/jpeg")
+                .with_transparent(true)
+                .tile_url(tile_id)
+        );
+    }
+
+    #[test]
+    fn query_values_are_escaped() {
+        let url = Wms::new(
+            "https://maps.example.com/wms",
+            ["roads & rails", "a+b"],
+            Attribution::new("Example", "https://example.com/"),
+        )
+        .with_styles(["dark mode", ""])
+        .with_format("image/png; mode=8bit")
+        .tile_url(TileId {
+            x: 0,
+            y: 0,
+            zoom: 0,
+        });
+
+        assert!(url.contains(
+            "&LAYERS=roads%20%26%20rails,a%2Bb&STYLES=dark%20mode,\
+            &FORMAT=image/png%3B%20mode%3D8bit&"
+        ));
+    }
+
+    #[test]
+    fn hidpi_images_are_bigger() {
+        assert!(source()
+            .scaled_tile_url(
+                TileId {
+                    x: 0,
+                    y: 0,
+                    zoom: 0
+                },
+                2
+            )
+            .ends_with("&WIDTH=512&HEIGHT=512"));
+
+        let source = source().with_tile_size(512);
+        assert_eq!(512, source.tile_size());
+        assert!(source
+            .scaled_tile_url(
+                TileId {
+                    x: 0,
+                    y: 0,
+                    zoom: 0
+                },
+                2
+            )
+            .ends_with("&WIDTH=1024&HEIGHT=1024"));
+    }
+
+    #[test]
+    fn existing_query_is_extended() {
+        let source = Wms::new(
+            "https://maps.example.com/service?map=roads.map",
+            ["roads"],
+            Attribution::new("Example", "https://example.com/"),
+        );
+
+        assert!(source
+            .tile_url(TileId {
+                x: 0,
+                y: 0,
+                zoom: 0
+            })
+            .starts_with("https://maps.example.com/service?map=roads.map&SERVICE=WMS&"));
+    }
+}
@@ -0,0 +1,806 @@
+//! Tiles from any [WMTS](https://www.ogc.org/standard/wmts/) server, configured from its
+//! GetCapabilities document.
+//!
+//! ```
+//! # use walkers::providers::wmts::{Capabilities, Wmts};
+//! # let xml = include_str!("wmts_capabilities.xml");
+//! // Typically downloaded from the server, e.g. https://example.com/wmts?REQUEST=GetCapabilities.
+//! let capabilities = Capabilities::parse(xml).unwrap();
+//! let source = Wmts::new(&capabilities, "ORTO").unwrap();
+//! ```
+//!
+//! Layers without a Web Mercator tile matrix set come with their own [`crate::TileGrid`], which
+//! needs to be given to [`crate::Map::with_grid`].
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use roxmltree::Node;
+
+use crate::{
+    grid::{Crs, Level, TileGrid, TransverseMercator, WebMercator, Wgs84},
+    mercator::{TileId, WEB_MERCATOR_EXTENT},
+    providers::{query_separator, query_value, Attribution, TileSource},
+};
+
+/// Scale denominator of the zoom level 0 in the Web Mercator, assuming 0.28 mm pixels, as in
+/// the "GoogleMapsCompatible" well-known scale set.
+const ZOOM_0_SCALE_DENOMINATOR: f64 = 559_082_264.028_717_8;
+
+/// Size of a pixel (in meters) which the scale denominators are calculated for.
+const PIXEL_SIZE: f64 = 0.000_28;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WmtsError {
+    #[error(transparent)]
+    Xml(#[from] roxmltree::Error),
+
+    #[error("invalid capabilities: {0}")]
+    Invalid(String),
+
+    #[error("layer '{0}' not found")]
+    LayerNotFound(String),
+
+    #[error("layer '{0}' has no tile matrix set in a supported CRS")]
+    UnsupportedCrs(String),
+
+    #[error("layer '{0}' has neither ResourceURL, nor KVP GetTile endpoint")]
+    NoEndpoint(String),
+}
+
+/// Parsed GetCapabilities document. Only the parts needed to get the tiles are kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    /// Name of the service's provider, typically used as the attribution.
+    pub provider: Option<String>,
+
+    /// Provider's website.
+    pub provider_site: Option<String>,
+
+    /// Endpoint of the GetTile operation using key-value pairs encoding.
+    pub get_tile_url: Option<String>,
+
+    pub layers: Vec<Layer>,
+    pub tile_matrix_sets: Vec<TileMatrixSet>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub identifier: String,
+    pub title: Option<String>,
+    pub styles: Vec<Style>,
+
+    /// MIME types, e.g. `image/png`.
+    pub formats: Vec<String>,
+
+    /// Identifiers of the [`TileMatrixSet`]s this layer is available in.
+    pub tile_matrix_sets: Vec<String>,
+
+    /// URL templates for the RESTful encoding.
+    pub resource_urls: Vec<ResourceUrl>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    pub identifier: String,
+    pub is_default: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceUrl {
+    pub format: String,
+    pub template: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileMatrixSet {
+    pub identifier: String,
+    pub supported_crs: String,
+    pub tile_matrices: Vec<TileMatrix>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileMatrix {
+    pub identifier: String,
+    pub scale_denominator: f64,
+
+    /// In the units of the CRS.
+    pub top_left_corner: (f64, f64),
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub matrix_width: u32,
+    pub matrix_height: u32,
+}
+
+impl TileMatrix {
+    /// Zoom level this matrix corresponds to, if it is aligned with the Web Mercator tiles.
+    fn web_mercator_zoom(&self) -> Option<u8> {
+        let zoom = (ZOOM_0_SCALE_DENOMINATOR / self.scale_denominator).log2();
+        let (left, top) = self.top_left_corner;
+
+        // Top left corner should match up to a meter, at least.
+        let aligned = (zoom - zoom.round()).abs() < 0.01
+            && (left + WEB_MERCATOR_EXTENT).abs() < 1.
+            && (top - WEB_MERCATOR_EXTENT).abs() < 1.
+            && self.tile_width == 256
+            && self.tile_height == 256;
+
+        (aligned && (0. ..=30.).contains(&zoom.round())).then_some(zoom.round() as u8)
+    }
+}
+
+impl TileMatrixSet {
+    /// EPSG code of the CRS, e.g. "3857".
+    fn epsg_code(&self) -> &str {
+        // E.g. "EPSG:3857", "urn:ogc:def:crs:EPSG::3857" or "urn:ogc:def:crs:EPSG:6.18.3:3857".
+        self.supported_crs.rsplit(':').next().unwrap_or_default()
+    }
+
+    fn is_web_mercator(&self) -> bool {
+        matches!(self.epsg_code(), "3857" | "900913")
+    }
+
+    /// Built-in CRS matching the supported one.
+    fn known_crs(&self) -> Option<Arc<dyn Crs>> {
+        match self.epsg_code() {
+            "3857" | "900913" => Some(Arc::new(WebMercator)),
+            "4326" => Some(Arc::new(Wgs84)),
+            "2180" => Some(Arc::new(TransverseMercator::puwg_1992())),
+            _ => None,
+        }
+    }
+
+    /// Grid of the tile matrices, in order, given the CRS they are defined in. All the matrices
+    /// need to share the top-left corner and the size of (square) tiles.
+    pub fn grid(&self, crs: Arc<dyn Crs>) -> Result<TileGrid, WmtsError> {
+        let invalid = |reason: &str| WmtsError::Invalid(format!("{} {}", self.identifier, reason));
+        let first = self
+            .tile_matrices
+            .first()
+            .ok_or_else(|| invalid("has no tile matrices"))?;
+
+        let consistent = self.tile_matrices.iter().all(|matrix| {
+            matrix.tile_width == first.tile_width
+                && matrix.tile_height == first.tile_width
+                && (matrix.top_left_corner.0 - first.top_left_corner.0).abs() < 1e-6
+                && (matrix.top_left_corner.1 - first.top_left_corner.1).abs() < 1e-6
+        });
+        if !consistent {
+            return Err(invalid(
+                "has tile matrices of different corners or tile sizes",
+            ));
+        }
+
+        let (x, y) = first.top_left_corner;
+        let origin = if crs.northing_first() {
+            geo_types::coord! { x: y, y: x }
+        } else {
+            geo_types::coord! { x: x, y: y }
+        };
+
+        let levels = self
+            .tile_matrices
+            .iter()
+            .map(|matrix| Level {
+                resolution: matrix.scale_denominator * PIXEL_SIZE / crs.meters_per_unit(),
+                matrix_width: matrix.matrix_width,
+                matrix_height: matrix.matrix_height,
+            })
+            .collect();
+
+        Ok(TileGrid::new(crs, origin, first.tile_width, levels))
+    }
+
+    /// Tile matrices, by their zoom level.
+    fn web_mercator_zoom_levels(&self) -> BTreeMap<u8, String> {
+        if !self.is_web_mercator() {
+            return BTreeMap::new();
+        }
+
+        self.tile_matrices
+            .iter()
+            .filter_map(|matrix| Some((matrix.web_mercator_zoom()?, matrix.identifier.clone())))
+            .collect()
+    }
+}
+
+impl Capabilities {
+    pub fn parse(xml: &str) -> Result<Self, WmtsError> {
+        let document = roxmltree::Document::parse(xml)?;
+        let root = document.root_element();
+
+        let provider = descendant(root, "ProviderName").and_then(text);
+        let provider_site = descendant(root, "ProviderSite").and_then(href);
+
+        let get_tile_url = descendants(root, "Operation")
+            .find(|operation| operation.attribute("name") == Some("GetTile"))
+            .into_iter()
+            .flat_map(|operation| descendants(operation, "Get"))
+            .find(|get| {
+                // Encoding is KVP, unless stated otherwise.
+                let mut encodings = descendants(*get, "Value").filter_map(text).peekable();
+                encodings.peek().is_none() || encodings.any(|encoding| encoding == "KVP")
+            })
+            .and_then(href);
+
+        let contents = child(root, "Contents")
+            .ok_or_else(|| WmtsError::Invalid("missing Contents".to_owned()))?;
+
+        let layers = children(contents, "Layer")
+            .map(parse_layer)
+            .collect::<Result<_, _>>()?;
+
+        let tile_matrix_sets = children(contents, "TileMatrixSet")
+            .map(parse_tile_matrix_set)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            provider,
+            provider_site,
+            get_tile_url,
+            layers,
+            tile_matrix_sets,
+        })
+    }
+
+    pub fn layer(&self, identifier: &str) -> Option<&Layer> {
+        self.layers
+            .iter()
+            .find(|layer| layer.identifier == identifier)
+    }
+}
+
+fn parse_layer(node: Node) -> Result<Layer, WmtsError> {
+    Ok(Layer {
+        identifier: required_text(node, "Identifier")?,
+        title: child(node, "Title").and_then(text),
+        styles: children(node, "Style")
+            .map(|style| {
+                Ok(Style {
+                    identifier: required_text(style, "Identifier")?,
+                    is_default: style.attribute("isDefault") == Some("true"),
+                })
+            })
+            .collect::<Result<_, WmtsError>>()?,
+        formats: children(node, "Format").filter_map(text).collect(),
+        tile_matrix_sets: children(node, "TileMatrixSetLink")
+            .filter_map(|link| child(link, "TileMatrixSet").and_then(text))
+            .collect(),
+        resource_urls: children(node, "ResourceURL")
+            .filter(|url| url.attribute("resourceType") == Some("tile"))
+            .filter_map(|url| {
+                Some(ResourceUrl {
+                    format: url.attribute("format")?.to_owned(),
+                    template: url.attribute("template")?.to_owned(),
+                })
+            })
+            .collect(),
+    })
+}
+
+fn parse_tile_matrix_set(node: Node) -> Result<TileMatrixSet, WmtsError> {
+    Ok(TileMatrixSet {
+        identifier: required_text(node, "Identifier")?,
+        supported_crs: required_text(node, "SupportedCRS")?,
+        tile_matrices: children(node, "TileMatrix")
+            .map(parse_tile_matrix)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn parse_tile_matrix(node: Node) -> Result<TileMatrix, WmtsError> {
+    let identifier = required_text(node, "Identifier")?;
+    let invalid = |name: &str| WmtsError::Invalid(format!("{} of {}", name, identifier));
+
+    let number = |name: &str| {
+        required_text(node, name)?
+            .parse::<f64>()
+            .map_err(|_| invalid(name))
+    };
+
+    let top_left_corner = required_text(node, "TopLeftCorner")?;
+    let top_left_corner = match top_left_corner
+        .split_whitespace()
+        .map(str::parse::<f64>)
+        .collect::<Result<Vec<_>, _>>()
+        .as_deref()
+    {
+        Ok([x, y]) => (*x, *y),
+        _ => return Err(invalid("TopLeftCorner")),
+    };
+
+    Ok(TileMatrix {
+        scale_denominator: number("ScaleDenominator")?,
+        top_left_corner,
+        tile_width: number("TileWidth")? as u32,
+        tile_height: number("TileHeight")? as u32,
+        matrix_width: number("MatrixWidth")? as u32,
+        matrix_height: number("MatrixHeight")? as u32,
+        identifier,
+    })
+}
+
+// Elements are matched by their local names, as servers are not consistent about the namespaces.
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn child<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+fn descendants<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.descendants()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn descendant<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> Option<Node<'a, 'input>> {
+    descendants(node, name).next()
+}
+
+fn text(node: Node) -> Option<String> {
+    node.text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_owned)
+}
+
+fn required_text(node: Node, name: &str) -> Result<String, WmtsError> {
+    child(node, name).and_then(text).ok_or_else(|| {
+        WmtsError::Invalid(format!("missing {} in {}", name, node.tag_name().name()))
+    })
+}
+
+fn href(node: Node) -> Option<String> {
+    node.attributes()
+        .find(|attribute| attribute.name() == "href")
+        .map(|attribute| attribute.value().to_owned())
+}
+
+/// How the tile URLs are built.
+#[derive(Clone, Debug)]
+enum Encoding {
+    /// ResourceURL template.
+    Rest(String),
+
+    /// GetTile endpoint.
+    Kvp(String),
+}
+
+/// Tiles of a single WMTS layer.
+#[derive(Clone, Debug)]
+pub struct Wmts {
+    encoding: Encoding,
+    layer: String,
+    style: String,
+    format: String,
+    tile_matrix_set: String,
+
+    /// Tile matrix identifiers, by their zoom level.
+    tile_matrices: BTreeMap<u8, String>,
+    grid: TileGrid,
+    attributions: Vec<Attribution>,
+}
+
+impl Wmts {
+    /// Source for the given layer, using its default style and preferring PNG format. RESTful
+    /// encoding is used if the server supports it. Provider from the capabilities is used as the
+    /// attribution.
+    ///
+    /// Tile matrix sets aligned with the Web Mercator tiles are preferred, as they can be drawn
+    /// together with the other layers. Otherwise, the first one in a built-in CRS (EPSG:4326
+    /// or EPSG:2180) is used, see [`Wmts::grid`].
+    pub fn new(capabilities: &Capabilities, layer: &str) -> Result<Self, WmtsError> {
+        let layer = find_layer(capabilities, layer)?;
+        let tile_matrix_sets = || {
+            capabilities
+                .tile_matrix_sets
+                .iter()
+                .filter(|set| layer.tile_matrix_sets.contains(&set.identifier))
+        };
+
+        // The most detailed tile matrix set wins.
+        let web_mercator = tile_matrix_sets()
+            .map(|set| (set.identifier.clone(), set.web_mercator_zoom_levels()))
+            .filter(|(_, tile_matrices)| !tile_matrices.is_empty())
+            .max_by_key(|(_, tile_matrices)| tile_matrices.len());
+
+        if let Some((tile_matrix_set, tile_matrices)) = web_mercator {
+            return Self::from_tile_matrix_set(
+                capabilities,
+                layer,
+                tile_matrix_set,
+                tile_matrices,
+                TileGrid::default(),
+            );
+        }
+
+        let (set, crs) = tile_matrix_sets()
+            .find_map(|set| Some((set, set.known_crs()?)))
+            .ok_or_else(|| WmtsError::UnsupportedCrs(layer.identifier.clone()))?;
+        Self::in_tile_matrix_set(capabilities, layer, set, crs)
+    }
+
+    /// Source for the given layer in a CRS which is not built-in, e.g. one of the polar
+    /// stereographic projections. Layer's tile matrix set with the same EPSG code is used.
+    pub fn with_crs(
+        capabilities: &Capabilities,
+        layer: &str,
+        crs: Arc<dyn Crs>,
+    ) -> Result<Self, WmtsError> {
+        let layer = find_layer(capabilities, layer)?;
+        let epsg_code = crs.code().rsplit(':').next().unwrap_or_default().to_owned();
+
+        let set = capabilities
+            .tile_matrix_sets
+            .iter()
+            .filter(|set| layer.tile_matrix_sets.contains(&set.identifier))
+            .find(|set| set.epsg_code() == epsg_code)
+            .ok_or_else(|| WmtsError::UnsupportedCrs(layer.identifier.clone()))?;
+        Self::in_tile_matrix_set(capabilities, layer, set, crs)
+    }
+
+    /// Source using all tile matrices of the set, with levels numbered by their order.
+    fn in_tile_matrix_set(
+        capabilities: &Capabilities,
+        layer: &Layer,
+        set: &TileMatrixSet,
+        crs: Arc<dyn Crs>,
+    ) -> Result<Self, WmtsError> {
+        let tile_matrices = set
+            .tile_matrices
+            .iter()
+            .enumerate()
+            .map(|(level, matrix)| (level as u8, matrix.identifier.clone()))
+            .collect();
+
+        Self::from_tile_matrix_set(
+            capabilities,
+            layer,
+            set.identifier.clone(),
+            tile_matrices,
+            set.grid(crs)?,
+        )
+    }
+
+    fn from_tile_matrix_set(
+        capabilities: &Capabilities,
+        layer: &Layer,
+        tile_matrix_set: String,
+        tile_matrices: BTreeMap<u8, String>,
+        grid: TileGrid,
+    ) -> Result<Self, WmtsError> {
+        let style = layer
+            .styles
+            .iter()
+            .find(|style| style.is_default)
+            .or(layer.styles.first())
+            .map(|style| style.identifier.clone())
+            .unwrap_or_else(|| "default".to_owned());
+
+        let format = layer
+            .formats
+            .iter()
+            .find(|format| format.as_str() == "image/png")
+            .or(layer.formats.first())
+            .cloned()
+            .unwrap_or_else(|| "image/png".to_owned());
+
+        let attributions = capabilities
+            .provider
+            .iter()
+            .map(|provider| {
+                Attribution::new(
+                    provider,
+                    capabilities.provider_site.clone().unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        let mut wmts = Self {
+            encoding: Encoding::Kvp(String::new()),
+            layer: layer.identifier.clone(),
+            style,
+            format: String::new(),
+            tile_matrix_set,
+            tile_matrices,
+            grid,
+            attributions,
+        };
+        wmts.encoding = wmts.encoding_for(capabilities, &format)?;
+        wmts.format = format;
+        Ok(wmts)
+    }
+
+    /// Use another style than the default one.
+    pub fn with_style(mut self, style: impl Into<String>) -> Self {
+        self.style = style.into();
+        self
+    }
+
+    /// Use another format (MIME type), e.g. `image/jpeg`.
+    pub fn with_format(
+        mut self,
+        capabilities: &Capabilities,
+        format: impl Into<String>,
+    ) -> Result<Self, WmtsError> {
+        let format = format.into();
+        self.encoding = self.encoding_for(capabilities, &format)?;
+        self.format = format;
+        Ok(self)
+    }
+
+    /// Replace the attribution taken from the capabilities.
+    pub fn with_attribution(mut self, attribution: Attribution) -> Self {
+        self.attributions = vec![attribution];
+        self
+    }
+
+    /// Zoom levels the tiles are available at.
+    pub fn zoom_levels(&self) -> impl Iterator<Item = u8> + '_ {
+        self.tile_matrices.keys().copied()
+    }
+
+    /// Layout of the tiles. Unless it is the Web Mercator, it needs to be given to
+    /// [`crate::Map::with_grid`].
+    pub fn grid(&self) -> &TileGrid {
+        &self.grid
+    }
+
+    fn encoding_for(
+        &self,
+        capabilities: &Capabilities,
+        format: &str,
+    ) -> Result<Encoding, WmtsError> {
+        let layer = find_layer(capabilities, &self.layer)?;
+
+        if let Some(resource_url) = layer.resource_urls.iter().find(|url| url.format == format) {
+            Ok(Encoding::Rest(resource_url.template.clone()))
+        } else if let Some(url) = &capabilities.get_tile_url {
+            Ok(Encoding::Kvp(url.clone()))
+        } else {
+            Err(WmtsError::NoEndpoint(self.layer.clone()))
+        }
+    }
+}
+
+impl TileSource for Wmts {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        // Server will respond with an error for zoom levels it does not have.
+        let tile_matrix = self
+            .tile_matrices
+            .get(&tile_id.zoom)
+            .cloned()
+            .unwrap_or_else(|| tile_id.zoom.to_string());
+
+        match &self.encoding {
+            Encoding::Rest(template) => template
+                .replace("{TileMatrixSet}", &self.tile_matrix_set)
+                .replace("{TileMatrix}", &tile_matrix)
+                .replace("{TileRow}", &tile_id.y.to_string())
+                .replace("{TileCol}", &tile_id.x.to_string())
+                .replace("{Style}", &self.style)
+                .replace("{Layer}", &self.layer),
+            // Identifiers come from the capabilities document, so they might be anything.
+            Encoding::Kvp(endpoint) => {
+                format!(
+                    "{}{}SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER={}&STYLE={}&FORMAT={}\
+                    &TILEMATRIXSET={}&TILEMATRIX={}&TILEROW={}&TILECOL={}",
+                    endpoint,
+                    query_separator(endpoint),
+                    query_value(&self.layer),
+                    query_value(&self.style),
+                    query_value(&self.format),
+                    query_value(&self.tile_matrix_set),
+                    query_value(&tile_matrix),
+                    tile_id.y,
+                    tile_id.x
+                )
+            }
+        }
+    }
+
+    fn attribution(&self) -> Attribution {
+        super::first_attribution(&self.attributions)
+    }
+
+    fn attributions(&self) -> Vec<Attribution> {
+        self.attributions.clone()
+    }
+
+    fn tile_size(&self) -> u32 {
+        self.grid.tile_size()
+    }
+
+    fn grid(&self) -> TileGrid {
+        self.grid.clone()
+    }
+}
+
+fn find_layer<'a>(capabilities: &'a Capabilities, layer: &str) -> Result<&'a Layer, WmtsError> {
+    capabilities
+        .layer(layer)
+        .ok_or_else(|| WmtsError::LayerNotFound(layer.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> Capabilities {
+        Capabilities::parse(include_str!("wmts_capabilities.xml")).unwrap()
+    }
+
+    static TILE_ID: TileId = TileId {
+        x: 1,
+        y: 2,
+        zoom: 2,
+    };
+
+    #[test]
+    fn parsing_capabilities() {
+        let capabilities = capabilities();
+
+        assert_eq!(
+            Some("Example Mapping Agency"),
+            capabilities.provider.as_deref()
+        );
+        assert_eq!(
+            Some("https://maps.example.com/wmts?"),
+            capabilities.get_tile_url.as_deref()
+        );
+        assert_eq!(4, capabilities.layers.len());
+        assert_eq!(4, capabilities.tile_matrix_sets.len());
+
+        let roads = capabilities.layer("ROADS").unwrap();
+        assert_eq!(Some("Roads"), roads.title.as_deref());
+        assert_eq!(
+            vec![
+                Style {
+                    identifier: "light".to_owned(),
+                    is_default: false
+                },
+                Style {
+                    identifier: "dark".to_owned(),
+                    is_default: true
+                }
+            ],
+            roads.styles
+        );
+        assert_eq!(vec!["GoogleMapsCompatible"], roads.tile_matrix_sets);
+        assert_eq!(1, roads.resource_urls.len());
+
+        let matrix = &capabilities.tile_matrix_sets[1].tile_matrices[1];
+        assert_eq!("EPSG:3857:1", matrix.identifier);
+        assert_eq!((2, 2), (matrix.matrix_width, matrix.matrix_height));
+        assert_eq!(Some(1), matrix.web_mercator_zoom());
+
+        // Not a Web Mercator.
+        assert!(capabilities.tile_matrix_sets[0]
+            .web_mercator_zoom_levels()
+            .is_empty());
+    }
+
+    #[test]
+    fn kvp_tile_urls() {
+        let source = Wmts::new(&capabilities(), "ORTO").unwrap();
+
+        assert_eq!(vec![0, 1, 2], source.zoom_levels().collect::<Vec<_>>());
+        assert_eq!(
+            "https://maps.example.com/wmts?SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0&LAYER=ORTO\
+            &STYLE=default&FORMAT=image/png&TILEMATRIXSET=EPSG:3857&TILEMATRIX=EPSG:3857:2\
+            &TILEROW=2&TILECOL=1",
+            source.tile_url(TILE_ID)
+        );
+        assert_eq!(
+            vec![Attribution::new(
+                "Example Mapping Agency",
+                "https://maps.example.com/"
+            )],
+            source.attributions()
+        );
+
+        let source = source
+            .with_format(&capabilities(), "image/jpeg")
+            .unwrap()
+            .with_style("fancy");
+        assert!(source
+            .tile_url(TILE_ID)
+            .contains("&STYLE=fancy&FORMAT=image/jpeg&"));
+
+        let source = source.with_style("black & white");
+        assert!(source
+            .tile_url(TILE_ID)
+            .contains("&STYLE=black%20%26%20white&"));
+    }
+
+    #[test]
+    fn rest_tile_urls() {
+        let source = Wmts::new(&capabilities(), "ROADS").unwrap();
+        assert_eq!(
+            "https://tiles.example.com/roads/dark/GoogleMapsCompatible/1/0/1.png",
+            source.tile_url(TileId {
+                x: 1,
+                y: 0,
+                zoom: 1
+            })
+        );
+    }
+
+    #[test]
+    fn layers_in_other_crs() {
+        let source = Wmts::new(&capabilities(), "CADASTRE").unwrap();
+        assert_eq!(vec![0, 1], source.zoom_levels().collect::<Vec<_>>());
+        assert!(source
+            .tile_url(TileId {
+                x: 0,
+                y: 0,
+                zoom: 1
+            })
+            .contains("&TILEMATRIX=EPSG:2180:1&"));
+
+        // Corner is given as northing, easting.
+        let grid = source.grid();
+        assert_eq!("EPSG:2180", grid.crs().code());
+        assert_eq!(
+            geo_types::coord! { x: 100_000., y: 850_000. },
+            grid.origin()
+        );
+        assert_eq!(512, grid.tile_size());
+        assert_eq!(512, source.tile_size());
+        approx::assert_relative_eq!(8400., grid.levels()[0].resolution);
+        approx::assert_relative_eq!(4200., grid.levels()[1].resolution);
+
+        assert!(matches!(
+            Wmts::new(&capabilities(), "SEA_ICE"),
+            Err(WmtsError::UnsupportedCrs(_))
+        ));
+        assert!(matches!(
+            Wmts::new(&capabilities(), "NOPE"),
+            Err(WmtsError::LayerNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn layers_in_custom_crs() {
+        /// Placeholder for the NSIDC Sea Ice Polar Stereographic North.
+        struct PolarStereographic;
+
+        impl Crs for PolarStereographic {
+            fn code(&self) -> &str {
+                "EPSG:3413"
+            }
+
+            fn project(&self, _: crate::Position) -> geo_types::Coord {
+                geo_types::coord! { x: 0., y: 0. }
+            }
+
+            fn unproject(&self, _: geo_types::Coord) -> crate::Position {
+                crate::Position::new(-45., 90.)
+            }
+        }
+
+        let source =
+            Wmts::with_crs(&capabilities(), "SEA_ICE", Arc::new(PolarStereographic)).unwrap();
+        let grid = source.grid();
+        assert_eq!("EPSG:3413", grid.crs().code());
+        assert_eq!(
+            geo_types::coord! { x: -4_194_304., y: 4_194_304. },
+            grid.origin()
+        );
+        approx::assert_relative_eq!(8192., grid.levels()[0].resolution);
+
+        // There is no such tile matrix set.
+        assert!(matches!(
+            Wmts::with_crs(&capabilities(), "CADASTRE", Arc::new(PolarStereographic)),
+            Err(WmtsError::UnsupportedCrs(_))
+        ));
+    }
+}
@@ -1,56 +1,269 @@
-use std::collections::hash_map::Entry;
-use std::{collections::HashMap, sync::Arc};
+use std::collections::HashMap;
+use std::sync::Arc;
 
-use egui::{pos2, Color32, Context, Mesh, Rect, Vec2};
+use egui::{Color32, Context, Mesh, Rect, Shape};
 use egui_extras::RetainedImage;
 
-use crate::download::download_continuously;
+use crate::cache::{CacheStats, Capacity, Lru};
+use crate::disk_cache::DiskCache;
+use crate::download::{
+    download_continuously, HttpFetcher, HttpOptions, Outcome, Request, Retry, Source, Viewport,
+};
+use crate::grid::TileGrid;
+use crate::http_cache::{now, CachePolicy, MIN_FRESHNESS};
 use crate::io::Runtime;
 use crate::mercator::TileId;
-use crate::providers::{Attribution, TileSource};
+use crate::providers::{Attribution, TileFetcher, TileSource};
+#[cfg(feature = "mvt")]
+use crate::vector::{VectorStyle, VectorTile};
 
 #[derive(Clone)]
 pub struct Tile {
-    image: Arc<RetainedImage>,
+    content: Content,
+}
+
+#[derive(Clone)]
+enum Content {
+    Image(Arc<RetainedImage>),
+
+    #[cfg(feature = "mvt")]
+    Vector(Arc<VectorTile>),
+}
+
+/// How the bytes of the tiles are decoded.
+#[derive(Clone, Default)]
+pub(crate) enum Format {
+    #[default]
+    Image,
+
+    #[cfg(feature = "mvt")]
+    Vector {
+        style: Arc<VectorStyle>,
+
+        /// Line widths are given in pixels, and tiles are tessellated in their own coordinates.
+        tile_size: u32,
+    },
 }
 
 impl Tile {
     pub(crate) fn from_image_bytes(image: &[u8]) -> Result<Self, String> {
         RetainedImage::from_image_bytes("debug_name", image).map(|image| Self {
-            image: Arc::new(image),
+            content: Content::Image(Arc::new(image)),
         })
     }
 
-    pub fn rect(&self, screen_position: Vec2) -> Rect {
-        let tile_size = pos2(self.image.width() as f32, self.image.height() as f32);
-        Rect::from_two_pos(
-            screen_position.to_pos2(),
-            (screen_position + tile_size.to_vec2()).to_pos2(),
-        )
+    pub(crate) fn from_color_image(image: egui::ColorImage) -> Self {
+        Self {
+            content: Content::Image(Arc::new(RetainedImage::from_color_image(
+                "debug_name",
+                image,
+            ))),
+        }
     }
 
-    pub fn mesh(&self, screen_position: Vec2, ctx: &Context) -> Mesh {
-        let mut mesh = Mesh::with_texture(self.image.texture_id(ctx));
-        mesh.add_rect_with_uv(
-            self.rect(screen_position),
-            Rect::from_min_max(pos2(0., 0.0), pos2(1.0, 1.0)),
-            Color32::WHITE,
-        );
-        mesh
+    #[cfg_attr(not(feature = "mvt"), allow(unused_variables))]
+    pub(crate) fn decode(bytes: &[u8], tile_id: TileId, format: &Format) -> Result<Self, String> {
+        match format {
+            Format::Image => Self::from_image_bytes(bytes),
+            #[cfg(feature = "mvt")]
+            Format::Vector { style, tile_size } => {
+                VectorTile::decode(bytes, tile_id, *tile_size, style).map(|tile| Self {
+                    content: Content::Vector(Arc::new(tile)),
+                })
+            }
+        }
+    }
+
+    /// Approximate amount of memory taken by the decoded image.
+    pub(crate) fn size_in_bytes(&self) -> usize {
+        match &self.content {
+            Content::Image(image) => {
+                image.width() * image.height() * std::mem::size_of::<Color32>()
+            }
+            #[cfg(feature = "mvt")]
+            Content::Vector(tile) => tile.size_in_bytes(),
+        }
+    }
+
+    /// Shapes drawing the part of the tile given in `uv` coordinates, stretched over `rect`.
+    pub fn shapes(&self, rect: Rect, uv: Rect, ctx: &Context) -> Vec<Shape> {
+        match &self.content {
+            Content::Image(image) => {
+                let mut mesh = Mesh::with_texture(image.texture_id(ctx));
+                mesh.add_rect_with_uv(rect, uv, Color32::WHITE);
+                vec![Shape::mesh(mesh)]
+            }
+            #[cfg(feature = "mvt")]
+            Content::Vector(tile) => {
+                let full = uv == Rect::from_min_max(egui::Pos2::ZERO, egui::pos2(1., 1.));
+                let to_screen = |p: egui::Pos2| rect.min + (p - uv.min) / uv.size() * rect.size();
+
+                let mut mesh = if full {
+                    tile.mesh.clone()
+                } else {
+                    crate::vector::clip(&tile.mesh, uv)
+                };
+                for vertex in &mut mesh.vertices {
+                    vertex.pos = to_screen(vertex.pos);
+                }
+
+                let labels = tile
+                    .labels
+                    .iter()
+                    .filter(|label| uv.contains(label.position))
+                    .map(|label| {
+                        let galley = ctx.fonts(|fonts| {
+                            fonts.layout_no_wrap(
+                                label.text.clone(),
+                                egui::FontId::proportional(label.size),
+                                label.color,
+                            )
+                        });
+                        let position = to_screen(label.position) - galley.size() / 2.;
+                        let mut text = egui::epaint::TextShape::new(position, galley);
+                        text.override_text_color = Some(label.color);
+                        Shape::Text(text)
+                    });
+
+                std::iter::once(Shape::mesh(mesh)).chain(labels).collect()
+            }
+        }
+    }
+}
+
+/// Configuration of the [`Tiles`].
+#[derive(Clone, Debug)]
+pub struct TilesOptions {
+    /// Persistent cache, consulted before downloading a tile. Disabled by default.
+    pub disk_cache: Option<DiskCache>,
+
+    /// Limit of the in-memory cache. When exceeded, least recently used tiles are dropped,
+    /// together with their textures. It should be big enough to hold all the tiles visible on the
+    /// screen at once. Unlimited by default.
+    pub capacity: Option<Capacity>,
+
+    /// Maximum number of tiles downloaded at the same time. Default is 6, which is what web
+    /// browsers typically use per host.
+    pub parallelism: usize,
+
+    /// How failed downloads are retried.
+    pub retry: Retry,
+
+    /// User agent, additional headers, timeout, proxy, etc.
+    pub http: HttpOptions,
+
+    /// Decode the tiles as Mapbox Vector Tiles and draw them with this style, instead of
+    /// decoding them as images.
+    #[cfg(feature = "mvt")]
+    pub vector_style: Option<VectorStyle>,
+}
+
+impl TilesOptions {
+    #[cfg_attr(not(feature = "mvt"), allow(unused_variables))]
+    pub(crate) fn format(&self, tile_size: u32) -> Format {
+        #[cfg(feature = "mvt")]
+        if let Some(style) = &self.vector_style {
+            return Format::Vector {
+                style: Arc::new(style.clone()),
+                tile_size,
+            };
+        }
+
+        Format::Image
     }
 }
 
+impl Default for TilesOptions {
+    fn default() -> Self {
+        Self {
+            disk_cache: None,
+            capacity: None,
+            parallelism: 6,
+            retry: Retry::default(),
+            http: HttpOptions::default(),
+            #[cfg(feature = "mvt")]
+            vector_style: None,
+        }
+    }
+}
+
+/// Loading state of a single tile, see [`Tiles::state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TileState {
+    /// Tile was requested, but it is not available yet.
+    Pending,
+
+    /// Tile is in the cache, ready to be drawn.
+    Loaded,
+
+    /// Tile could not be loaded, and it will not be tried again until [`Tiles::clear_failed`].
+    Failed(String),
+
+    /// Source does not have this tile, e.g. offline data covers only some area, or the server
+    /// responded with `404 Not Found`. It is not reported as a failure.
+    Missing,
+}
+
+/// Aggregated loading status of all the tiles, see [`Tiles::status`]. Can be used to show
+/// a "loading" or "offline" indicator over the map.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Number of tiles requested, but not loaded (nor failed) yet.
+    pub in_flight: usize,
+
+    /// Number of tiles in the cache which could not be loaded.
+    pub failed: usize,
+
+    /// Most recent failure, if any.
+    pub last_error: Option<String>,
+}
+
+/// What is kept in the in-memory cache for each tile.
+enum Slot {
+    Pending,
+    Loaded {
+        tile: Tile,
+        policy: CachePolicy,
+
+        /// Tile expired (or is less detailed than needed) and a request for it is in
+        /// flight. Stale tile is still shown in the meantime.
+        revalidating: bool,
+
+        /// Scale factor the tile was requested with.
+        scale: u32,
+    },
+    Failed(String),
+    Missing,
+}
+
 /// Downloads and keeps cache of the tiles. It must persist between frames.
 pub struct Tiles {
-    attribution: Attribution,
+    attributions: Vec<Attribution>,
+
+    cache: Lru<TileId, Slot>,
+
+    /// Tiles requested from the IO thread, but not received yet, with their scale factor.
+    in_flight: HashMap<TileId, u32>,
+    last_error: Option<String>,
 
-    cache: HashMap<TileId, Option<Tile>>,
+    hits: u64,
+    misses: u64,
 
     /// Tiles to be downloaded by the IO thread.
-    request_tx: futures::channel::mpsc::Sender<TileId>,
+    request_tx: futures::channel::mpsc::Sender<Request>,
 
-    /// Tiles that got downloaded and should be put in the cache.
-    tile_rx: futures::channel::mpsc::Receiver<(TileId, Tile)>,
+    /// Tiles that got downloaded (or failed to) and should be put in the cache.
+    tile_rx: futures::channel::mpsc::Receiver<(TileId, Outcome)>,
+
+    /// Tiles visible on the screen, shared with the IO thread.
+    viewport: Viewport,
+
+    /// Scale factor of the tiles to request, matching screen's pixels per point.
+    scale: u32,
+    max_scale: u32,
+
+    /// Layout of the source's tiles.
+    grid: TileGrid,
 
     #[allow(dead_code)] // Significant Drop
     runtime: Runtime,
@@ -61,35 +274,122 @@ impl Tiles {
     where
         S: TileSource + Send + 'static,
     {
+        Self::with_options(source, TilesOptions::default(), egui_ctx)
+    }
+
+    /// Same as [`Tiles::new`], but allows enabling additional features, such as the disk cache.
+    ///
+    /// ```no_run
+    /// # use walkers::{DiskCache, Tiles, TilesOptions, providers::OpenStreetMap};
+    /// # let egui_ctx = egui::Context::default();
+    /// let tiles = Tiles::with_options(
+    ///     OpenStreetMap,
+    ///     TilesOptions {
+    ///         disk_cache: Some(DiskCache::new(".cache/osm").with_max_size(100 * 1024 * 1024)),
+    ///         ..Default::default()
+    ///     },
+    ///     egui_ctx,
+    /// );
+    /// ```
+    pub fn with_options<S>(source: S, options: TilesOptions, egui_ctx: Context) -> Self
+    where
+        S: TileSource + Send + 'static,
+    {
+        let attributions = source.attributions();
+        Self::spawn(
+            Source::Http(HttpFetcher::with_options(source, &options)),
+            attributions,
+            options,
+            egui_ctx,
+        )
+    }
+
+    /// Tiles coming from a [`TileFetcher`], such as [`crate::providers::InMemory`], instead of
+    /// being downloaded over HTTP. Disk cache and HTTP options are not used, as the fetcher has
+    /// its own, and [`TilesOptions::retry`] applies only to [`crate::providers::FetchError::Http`] failures.
+    ///
+    /// ```
+    /// # use walkers::{Tiles, TilesOptions, providers::InMemory};
+    /// # let egui_ctx = egui::Context::default();
+    /// let tiles = Tiles::with_fetcher(InMemory::default(), TilesOptions::default(), egui_ctx);
+    /// ```
+    pub fn with_fetcher<F>(fetcher: F, options: TilesOptions, egui_ctx: Context) -> Self
+    where
+        F: TileFetcher + Send + 'static,
+    {
+        let attributions = fetcher.attributions();
+        Self::spawn(
+            Source::Fetcher(Box::new(fetcher)),
+            attributions,
+            options,
+            egui_ctx,
+        )
+    }
+
+    fn spawn(
+        source: Source,
+        attributions: Vec<Attribution>,
+        options: TilesOptions,
+        egui_ctx: Context,
+    ) -> Self {
         // Minimum value which didn't cause any stalls while testing.
         let channel_size = 20;
 
         let (request_tx, request_rx) = futures::channel::mpsc::channel(channel_size);
         let (tile_tx, tile_rx) = futures::channel::mpsc::channel(channel_size);
-        let attribution = source.attribution();
-        let runtime = Runtime::new(download_continuously(source, request_rx, tile_tx, egui_ctx));
+        let cache = Lru::new(options.capacity);
+        let viewport = Viewport::default();
+        let max_scale = source.max_scale();
+        let grid = source.grid();
+        let runtime = Runtime::new(download_continuously(
+            source,
+            options,
+            viewport.clone(),
+            request_rx,
+            tile_tx,
+            egui_ctx,
+        ));
 
         Self {
-            attribution,
-            cache: Default::default(),
+            attributions,
+            cache,
+            in_flight: HashMap::new(),
+            last_error: None,
+            hits: 0,
+            misses: 0,
             request_tx,
             tile_rx,
+            viewport,
+            scale: 1,
+            max_scale,
+            grid,
             runtime,
         }
     }
 
-    /// Attribution of the source this tile cache pulls images from. Typically,
-    /// this should be displayed somewhere on the top of the map widget.
-    pub fn attribution(&self) -> Attribution {
-        self.attribution
+    /// Size (in pixels) of the source's tiles, see [`TileSource::tile_size`].
+    pub fn tile_size(&self) -> u32 {
+        self.grid.tile_size()
+    }
+
+    /// Layout of the source's tiles, see [`TileSource::grid`].
+    pub fn grid(&self) -> &TileGrid {
+        &self.grid
+    }
+
+    /// Attribution of the source this tile cache pulls images from. It should be displayed
+    /// somewhere on the map widget, e.g. using [`crate::Map::with_attribution`].
+    pub fn attributions(&self) -> &[Attribution] {
+        &self.attributions
     }
 
     /// Return a tile if already in cache, schedule a download otherwise.
     pub fn at(&mut self, tile_id: TileId) -> Option<Tile> {
         // Just take one at the time.
         match self.tile_rx.try_next() {
-            Ok(Some((tile_id, tile))) => {
-                self.cache.insert(tile_id, Some(tile));
+            Ok(Some((tile_id, outcome))) => {
+                let scale = self.in_flight.remove(&tile_id).unwrap_or(self.scale);
+                self.received(tile_id, scale, outcome);
             }
             Err(_) => {
                 // Just ignore. It means that no new tile was downloaded.
@@ -97,17 +397,194 @@ impl Tiles {
             Ok(None) => panic!("IO thread is dead"),
         }
 
-        match self.cache.entry(tile_id) {
-            Entry::Occupied(entry) => entry.get().clone(),
-            Entry::Vacant(entry) => {
-                if let Ok(()) = self.request_tx.try_send(tile_id) {
-                    log::debug!("Requested tile: {:?}", tile_id);
-                    entry.insert(None);
-                } else {
-                    log::debug!("Request queue is full.");
+        match self.cache.get_mut(&tile_id) {
+            Some(Slot::Loaded {
+                tile,
+                policy,
+                revalidating,
+                scale,
+            }) => {
+                let tile = tile.clone();
+                self.hits += 1;
+
+                // Less detailed tile is shown until the one matching the screen arrives. Sharper
+                // ones are kept, e.g. when the window moves to a screen with lower pixel density.
+                let rescale = *scale < self.scale;
+
+                if !*revalidating && (rescale || policy.is_expired(now())) {
+                    let request = Request {
+                        tile_id,
+                        stale: (!rescale).then(|| policy.clone()),
+                        scale: if rescale { self.scale } else { *scale },
+                    };
+                    let requested_scale = request.scale;
+                    if let Ok(()) = self.request_tx.try_send(request) {
+                        log::debug!("Requested revalidation of tile: {:?}", tile_id);
+                        *revalidating = true;
+                        self.in_flight.insert(tile_id, requested_scale);
+                    }
                 }
-                None
+
+                return Some(tile);
+            }
+            Some(_) => {
+                self.misses += 1;
+                return None;
+            }
+            None => (),
+        }
+
+        self.misses += 1;
+
+        let request = Request {
+            tile_id,
+            stale: None,
+            scale: self.scale,
+        };
+        if let Ok(()) = self.request_tx.try_send(request) {
+            log::debug!("Requested tile: {:?}", tile_id);
+            self.cache.insert(tile_id, Slot::Pending, 0);
+            self.in_flight.insert(tile_id, self.scale);
+        } else {
+            log::debug!("Request queue is full.");
+        }
+        None
+    }
+
+    /// Put the outcome of a request into the cache.
+    fn received(&mut self, tile_id: TileId, requested_scale: u32, outcome: Outcome) {
+        match (self.cache.get_mut(&tile_id), outcome) {
+            (_, Outcome::Loaded(tile, policy)) => {
+                let size = tile.size_in_bytes();
+                let slot = Slot::Loaded {
+                    tile,
+                    policy,
+                    revalidating: false,
+                    scale: requested_scale,
+                };
+                self.cache.insert(tile_id, slot, size);
+            }
+            (
+                Some(Slot::Loaded {
+                    policy,
+                    revalidating,
+                    ..
+                }),
+                Outcome::Revalidated(fresh),
+            ) => {
+                *policy = fresh;
+                *revalidating = false;
+            }
+            (
+                Some(Slot::Loaded {
+                    policy,
+                    revalidating,
+                    scale,
+                    ..
+                }),
+                Outcome::Failed(e),
+            ) => {
+                // Stale tile is better than no tile. Try again later, also when the source does
+                // not have it at the requested scale.
+                log::info!("Could not revalidate {:?}: {}", tile_id, e);
+                *policy = policy.clone().fresh_for_at_least(MIN_FRESHNESS, now());
+                *revalidating = false;
+                *scale = requested_scale;
+            }
+            (
+                Some(Slot::Loaded {
+                    policy,
+                    revalidating,
+                    scale,
+                    ..
+                }),
+                Outcome::Missing,
+            ) => {
+                *policy = policy.clone().fresh_for_at_least(MIN_FRESHNESS, now());
+                *revalidating = false;
+                *scale = requested_scale;
+            }
+            (Some(Slot::Loaded { revalidating, .. }), Outcome::Cancelled) => {
+                *revalidating = false;
             }
+            (_, Outcome::Failed(e)) => {
+                self.cache.insert(tile_id, Slot::Failed(e.clone()), 0);
+                self.last_error = Some(e);
+            }
+            (_, Outcome::Missing) => {
+                self.cache.insert(tile_id, Slot::Missing, 0);
+            }
+            (_, Outcome::Cancelled) => {
+                // It will be requested again, once it becomes visible.
+                self.cache.remove(&tile_id);
+            }
+            (_, Outcome::Revalidated(_)) => {
+                // Tile got evicted while being revalidated.
+            }
+        }
+    }
+
+    /// Loading state of the tile, or `None` if it was never requested (or got evicted from the
+    /// cache). Tiles which are being revalidated are reported as loaded.
+    pub fn state(&self, tile_id: TileId) -> Option<TileState> {
+        self.cache.peek(&tile_id).map(|slot| match slot {
+            Slot::Pending => TileState::Pending,
+            Slot::Loaded { .. } => TileState::Loaded,
+            Slot::Failed(e) => TileState::Failed(e.clone()),
+            Slot::Missing => TileState::Missing,
+        })
+    }
+
+    /// Aggregated loading status of all the tiles.
+    pub fn status(&self) -> Status {
+        Status {
+            in_flight: self.in_flight.len(),
+            failed: self
+                .cache
+                .values()
+                .filter(|slot| matches!(slot, Slot::Failed(_)))
+                .count(),
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    /// Forget about the tiles which could not be downloaded, so they are requested again the
+    /// next time they are needed. Useful e.g. when network connection becomes available again.
+    pub fn clear_failed(&mut self) {
+        self.cache
+            .retain(|_, slot| !matches!(slot, Slot::Failed(_)));
+        self.last_error = None;
+    }
+
+    /// Request tiles matching the screen's pixels per point, up to the source's
+    /// [`TileSource::max_scale`]. Already cached tiles are replaced as they are used.
+    /// [`crate::Map`] calls this at each frame.
+    pub(crate) fn set_pixels_per_point(&mut self, pixels_per_point: f32) {
+        self.scale = (pixels_per_point.ceil() as u32).clamp(1, self.max_scale);
+    }
+
+    /// Tell the IO thread which tiles are currently visible. Requests for other tiles, which are
+    /// still waiting in the queue, are dropped. [`crate::Map`] calls this at each frame.
+    pub(crate) fn set_viewport(&self, tiles: impl IntoIterator<Item = TileId>) {
+        self.viewport.set(tiles);
+    }
+
+    /// Return a tile if already in cache, without scheduling a download.
+    pub(crate) fn cached(&mut self, tile_id: TileId) -> Option<Tile> {
+        match self.cache.get(&tile_id) {
+            Some(Slot::Loaded { tile, .. }) => Some(tile.clone()),
+            _ => None,
+        }
+    }
+
+    /// Statistics of the in-memory cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.cache.evictions(),
+            tiles: self.cache.len(),
+            bytes: self.cache.size(),
         }
     }
 }
@@ -117,6 +594,7 @@ mod tests {
     use std::time::Duration;
 
     use super::*;
+    use crate::providers::{FetchError, InMemory, TileData};
 
     static TILE_ID: TileId = TileId {
         x: 1,
@@ -143,7 +621,7 @@ mod tests {
         }
 
         fn attribution(&self) -> Attribution {
-            Attribution { text: "", url: "" }
+            Attribution::new("", "")
         }
     }
 
@@ -176,22 +654,912 @@ mod tests {
         tile_mock.assert();
     }
 
-    fn assert_tile_is_empty_forever(tiles: &mut Tiles) {
-        // Should be None now, and forever.
-        assert!(tiles.at(TILE_ID).is_none());
-        std::thread::sleep(Duration::from_secs(1));
-        assert!(tiles.at(TILE_ID).is_none());
+    #[test]
+    fn many_tiles_are_downloaded_in_parallel() {
+        let _ = env_logger::try_init();
+
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        // Responses are slow, so that the downloads overlap.
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", mockito::Matcher::Any)
+            .with_chunked_body({
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                move |writer| {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(200));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    writer.write_all(include_bytes!("valid.png"))
+                }
+            })
+            .expect(10)
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            source,
+            TilesOptions {
+                parallelism: 4,
+                ..Default::default()
+            },
+            Context::default(),
+        );
+
+        // Request all of them at once.
+        let tile_ids: Vec<_> = (0..10).map(|x| TileId { x, ..TILE_ID }).collect();
+        for tile_id in &tile_ids {
+            tiles.at(*tile_id);
+        }
+        while tile_ids.iter().any(|tile_id| tiles.at(*tile_id).is_none()) {}
+
+        tile_mock.assert();
+        assert_eq!(4, max_in_flight.load(Ordering::SeqCst));
     }
 
     #[test]
-    fn tile_is_empty_forever_if_http_returns_error() {
+    fn tile_state_and_status_are_reported() {
         let _ = env_logger::try_init();
 
         let (mut server, source) = mockito_server();
+        let _tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        let _not_found_mock = server.mock("GET", "/3/2/2.png").with_status(404).create();
+        let _forbidden_mock = server.mock("GET", "/3/3/2.png").with_status(403).create();
+
         let mut tiles = Tiles::new(source, Context::default());
-        let tile_mock = server.mock("GET", "/3/1/2.png").with_status(404).create();
+        let missing = TileId { x: 2, ..TILE_ID };
+        let forbidden = TileId { x: 3, ..TILE_ID };
 
-        assert_tile_is_empty_forever(&mut tiles);
+        assert_eq!(None, tiles.state(TILE_ID));
+        assert_eq!(Status::default(), tiles.status());
+
+        tiles.at(TILE_ID);
+        tiles.at(missing);
+        tiles.at(forbidden);
+        assert_eq!(Some(TileState::Pending), tiles.state(TILE_ID));
+        assert_eq!(3, tiles.status().in_flight);
+
+        while tiles.status().in_flight > 0 {
+            tiles.at(TILE_ID);
+        }
+
+        assert_eq!(Some(TileState::Loaded), tiles.state(TILE_ID));
+        assert_eq!(Some(TileState::Missing), tiles.state(missing));
+        assert!(matches!(tiles.state(forbidden), Some(TileState::Failed(_))));
+
+        // Tiles which the server does not have are not errors.
+        let status = tiles.status();
+        assert_eq!(1, status.failed);
+        assert!(status.last_error.unwrap().contains("403"));
+
+        tiles.clear_failed();
+        assert_eq!(None, tiles.state(forbidden));
+        assert_eq!(Some(TileState::Missing), tiles.state(missing));
+        assert_eq!(Status::default(), tiles.status());
+    }
+
+    #[test]
+    fn tiles_which_are_not_visible_are_not_downloaded() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server.mock("GET", "/3/1/2.png").expect(0).create();
+        let visible_tile_mock = server
+            .mock("GET", "/3/2/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default());
+        let visible = TileId { x: 2, ..TILE_ID };
+        tiles.set_viewport([visible]);
+
+        tiles.at(TILE_ID);
+        while tiles.at(visible).is_none() || tiles.state(TILE_ID).is_some() {}
+
+        tile_mock.assert();
+        visible_tile_mock.assert();
+    }
+
+    #[test]
+    fn cache_statistics_are_tracked() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let _tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default());
+
+        while tiles.at(TILE_ID).is_none() {}
+        let stats = tiles.stats();
+        assert_eq!(1, stats.hits);
+        assert!(stats.misses >= 1);
+        assert_eq!(1, stats.tiles);
+        assert!(stats.bytes > 0);
+    }
+
+    #[test]
+    fn tiles_are_evicted_when_capacity_is_exceeded() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let _tile_mock = server
+            .mock("GET", mockito::Matcher::Any)
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            source,
+            TilesOptions {
+                capacity: Some(Capacity::Tiles(1)),
+                ..Default::default()
+            },
+            Context::default(),
+        );
+
+        while tiles.at(TILE_ID).is_none() {}
+
+        let other = TileId { x: 2, ..TILE_ID };
+        while tiles.at(other).is_none() {}
+
+        let stats = tiles.stats();
+        assert_eq!(1, stats.tiles);
+        assert!(stats.evictions >= 1);
+    }
+
+    #[test]
+    fn tile_is_read_from_disk_cache_in_next_session() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let options = || TilesOptions {
+            disk_cache: Some(DiskCache::new(directory.path())),
+            ..Default::default()
+        };
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(source, options(), Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+        drop(tiles);
+        tile_mock.assert();
+
+        // Server does not have the tile anymore, but it was cached.
+        let (mut server, source) = mockito_server();
+        let tile_mock = server.mock("GET", "/3/1/2.png").expect(0).create();
+
+        let mut tiles = Tiles::with_options(source, options(), Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn hidpi_tiles_replace_regular_ones() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let options = TilesOptions {
+            disk_cache: Some(DiskCache::new(directory.path())),
+            ..Default::default()
+        };
+
+        let mut server = mockito::Server::new();
+        let source = crate::providers::TemplateSource::new(
+            format!("{}/{{z}}/{{x}}/{{y}}{{r}}.png", server.url()),
+            Attribution::new("", ""),
+        )
+        .with_retina(true);
+        let regular_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        let hidpi_mock = server
+            .mock("GET", "/3/1/2@2x.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(source, options, Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+        regular_mock.assert();
+
+        // Regular tile is still shown, until the high resolution one arrives.
+        tiles.set_pixels_per_point(1.5);
+        assert!(tiles.at(TILE_ID).is_some());
+        assert_eq!(1, tiles.status().in_flight);
+        while tiles.status().in_flight > 0 {
+            assert!(tiles.at(TILE_ID).is_some());
+        }
+        hidpi_mock.assert();
+        assert!(directory.path().join("@2x/3/1/2.tile").exists());
+
+        // Source has nothing sharper.
+        tiles.set_pixels_per_point(3.);
+        tiles.at(TILE_ID);
+        assert_eq!(0, tiles.status().in_flight);
+
+        // Sharper tile is kept on a screen with lower pixel density.
+        tiles.set_pixels_per_point(1.);
+        assert!(tiles.at(TILE_ID).is_some());
+        assert_eq!(0, tiles.status().in_flight);
+        regular_mock.assert();
+    }
+
+    #[test]
+    fn expired_tile_is_revalidated() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+
+        // Mocks which did not receive expected number of hits take precedence.
+        let not_modified_mock = server
+            .mock("GET", "/3/1/2.png")
+            .match_header("if-none-match", "\"abc\"")
+            .with_status(304)
+            .with_header("cache-control", "max-age=3600")
+            .expect(1)
+            .create();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_header("cache-control", "no-cache")
+            .with_header("etag", "\"abc\"")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+
+        // Pretend that the minimum freshness has already passed.
+        if let Some(Slot::Loaded { policy, .. }) = tiles.cache.get_mut(&TILE_ID) {
+            policy.expires = Some(0);
+        }
+
+        // Stale tile is still available while being revalidated.
+        assert!(tiles.at(TILE_ID).is_some());
+        assert_eq!(1, tiles.status().in_flight);
+        assert_eq!(Some(TileState::Loaded), tiles.state(TILE_ID));
+
+        while tiles.status().in_flight > 0 {
+            assert!(tiles.at(TILE_ID).is_some());
+        }
+
+        // Now it is fresh for an hour.
+        assert!(tiles.at(TILE_ID).is_some());
+        assert_eq!(0, tiles.status().in_flight);
+
+        not_modified_mock.assert();
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn user_agent_and_additional_headers_are_sent() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .match_header("user-agent", "MyApp/1.0 (me@example.com)")
+            .match_header("authorization", "Bearer secret")
+            .match_header("x-custom", "custom")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            source,
+            TilesOptions {
+                http: HttpOptions {
+                    user_agent: "MyApp/1.0 (me@example.com)".to_owned(),
+                    headers: vec![
+                        ("Authorization".to_owned(), "Bearer secret".to_owned()),
+                        ("X-Custom".to_owned(), "custom".to_owned()),
+                    ],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Context::default(),
+        );
+
+        while tiles.at(TILE_ID).is_none() {}
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn default_user_agent_is_sent() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .match_header("user-agent", "Walkers")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn requests_go_through_proxy() {
+        let _ = env_logger::try_init();
+
+        let mut proxy = mockito::Server::new();
+        let tile_mock = proxy
+            .mock("GET", "/3/1/2.png")
+            .match_header("host", "tiles.example.com")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            TestSource::new("http://tiles.example.com".to_owned()),
+            TilesOptions {
+                http: HttpOptions {
+                    proxy: Some(proxy.url()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Context::default(),
+        );
+
+        while tiles.at(TILE_ID).is_none() {}
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn redirects_are_limited() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let redirect_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(302)
+            .with_header("location", "/moved.png")
+            .expect(1)
+            .create();
+        let tile_mock = server
+            .mock("GET", "/moved.png")
+            .with_body(include_bytes!("valid.png"))
+            .expect(0)
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            source,
+            TilesOptions {
+                http: HttpOptions {
+                    max_redirects: 0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Context::default(),
+        );
+
+        assert_tile_is_empty_forever(&mut tiles);
+        redirect_mock.assert();
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn slow_requests_time_out() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_chunked_body(|writer| {
+                std::thread::sleep(Duration::from_secs(2));
+                writer.write_all(include_bytes!("valid.png"))
+            })
+            .create();
+
+        let mut options = fast_retry();
+        options.retry.max_retries = 0;
+        options.http.timeout = Some(Duration::from_millis(100));
+        let mut tiles = Tiles::with_options(source, options, Context::default());
+
+        while tiles.status().last_error.is_none() {
+            assert!(tiles.at(TILE_ID).is_none());
+        }
+
+        assert!(tiles.status().last_error.unwrap().contains("timed out"));
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn invalid_http_options_fail_all_tiles() {
+        let _ = env_logger::try_init();
+
+        let mut tiles = Tiles::with_options(
+            GarbageSource,
+            TilesOptions {
+                http: HttpOptions {
+                    headers: vec![("Invalid Name".to_owned(), "value".to_owned())],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Context::default(),
+        );
+
+        while tiles.status().last_error.is_none() {
+            assert!(tiles.at(TILE_ID).is_none());
+        }
+
+        assert!(tiles
+            .status()
+            .last_error
+            .unwrap()
+            .contains("invalid HTTP client configuration"));
+    }
+
+    #[test]
+    fn cached_tiles_are_available_despite_invalid_http_options() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            source,
+            TilesOptions {
+                disk_cache: Some(DiskCache::new(directory.path())),
+                ..Default::default()
+            },
+            Context::default(),
+        );
+        while tiles.at(TILE_ID).is_none() {}
+        drop(tiles);
+        tile_mock.assert();
+
+        let mut tiles = Tiles::with_options(
+            TestSource::new(server.url()),
+            TilesOptions {
+                disk_cache: Some(DiskCache::new(directory.path())),
+                http: HttpOptions {
+                    headers: vec![("Invalid Name".to_owned(), "value".to_owned())],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Context::default(),
+        );
+        while tiles.at(TILE_ID).is_none() {}
+        assert_eq!(None, tiles.status().last_error);
+    }
+
+    #[test]
+    fn tiles_are_fetched_without_http() {
+        let _ = env_logger::try_init();
+
+        let fetcher =
+            InMemory::default().with_tile(TILE_ID, include_bytes!("valid.png").as_slice());
+        let mut tiles = Tiles::with_fetcher(fetcher, TilesOptions::default(), Context::default());
+
+        let missing = TileId { x: 2, ..TILE_ID };
+        tiles.at(missing);
+        while tiles.at(TILE_ID).is_none() {}
+
+        while tiles.state(missing) == Some(TileState::Pending) {
+            tiles.at(TILE_ID);
+        }
+        assert_eq!(Some(TileState::Missing), tiles.state(missing));
+        assert_eq!(Status::default(), tiles.status());
+    }
+
+    /// Draws each tile in a different shade of gray, or fails for the ones with odd `x`.
+    struct Generated;
+
+    impl TileFetcher for Generated {
+        fn fetch(&self, tile_id: TileId) -> crate::providers::TileFuture {
+            Box::pin(async move {
+                if tile_id.x % 2 == 1 {
+                    return Err(FetchError::Other("odd tile".into()));
+                }
+                let gray = Color32::from_gray(tile_id.zoom * 10);
+                Ok(Some(TileData::Image(egui::ColorImage::new(
+                    [256, 256],
+                    gray,
+                ))))
+            })
+        }
+
+        fn attributions(&self) -> Vec<Attribution> {
+            vec![Attribution::new("generated", "")]
+        }
+    }
+
+    #[test]
+    fn tiles_can_be_generated() {
+        let _ = env_logger::try_init();
+
+        let mut tiles = Tiles::with_fetcher(Generated, TilesOptions::default(), Context::default());
+        assert_eq!(&[Attribution::new("generated", "")], tiles.attributions());
+
+        let even = TileId { x: 2, ..TILE_ID };
+        tiles.at(TILE_ID);
+        while tiles.at(even).is_none() {}
+        assert_eq!(256 * 256 * 4, tiles.stats().bytes);
+
+        while tiles.state(TILE_ID) == Some(TileState::Pending) {
+            tiles.at(even);
+        }
+        assert_eq!(
+            Some(TileState::Failed("odd tile".to_owned())),
+            tiles.state(TILE_ID)
+        );
+    }
+
+    /// Remembers which tiles it was asked for.
+    #[derive(Clone, Default)]
+    struct Recording(Arc<std::sync::Mutex<Vec<TileId>>>);
+
+    impl TileFetcher for Recording {
+        fn fetch(&self, tile_id: TileId) -> crate::providers::TileFuture {
+            self.0.lock().unwrap().push(tile_id);
+            Box::pin(async move {
+                Ok(Some(TileData::Image(egui::ColorImage::new(
+                    [256, 256],
+                    Color32::GRAY,
+                ))))
+            })
+        }
+
+        fn attributions(&self) -> Vec<Attribution> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn tiles_which_are_not_visible_are_not_fetched() {
+        let _ = env_logger::try_init();
+
+        let fetcher = Recording::default();
+        let mut tiles =
+            Tiles::with_fetcher(fetcher.clone(), TilesOptions::default(), Context::default());
+        let visible = TileId { x: 2, ..TILE_ID };
+        tiles.set_viewport([visible]);
+
+        tiles.at(TILE_ID);
+        while tiles.at(visible).is_none() || tiles.state(TILE_ID).is_some() {}
+
+        assert_eq!(vec![visible], *fetcher.0.lock().unwrap());
+    }
+
+    #[test]
+    fn http_source_can_be_used_as_fetcher() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+        let _not_found_mock = server.mock("GET", "/3/2/2.png").with_status(404).create();
+
+        let fetcher = HttpFetcher::new(source, &HttpOptions::default()).unwrap();
+        let mut tiles = Tiles::with_fetcher(fetcher, TilesOptions::default(), Context::default());
+
+        let missing = TileId { x: 2, ..TILE_ID };
+        tiles.at(missing);
+        while tiles.at(TILE_ID).is_none() {}
+
+        while tiles.state(missing) == Some(TileState::Pending) {
+            tiles.at(TILE_ID);
+        }
+        assert_eq!(Some(TileState::Missing), tiles.state(missing));
+        assert_eq!(Status::default(), tiles.status());
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn http_fetcher_shares_the_disk_cache() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            source,
+            TilesOptions {
+                disk_cache: Some(DiskCache::new(directory.path())),
+                ..Default::default()
+            },
+            Context::default(),
+        );
+        while tiles.at(TILE_ID).is_none() {}
+        drop(tiles);
+        tile_mock.assert();
+
+        // Server does not have the tile anymore, but the fetcher finds it in the disk cache.
+        let (mut server, source) = mockito_server();
+        let tile_mock = server.mock("GET", "/3/1/2.png").expect(0).create();
+
+        let fetcher = HttpFetcher::new(source, &HttpOptions::default())
+            .unwrap()
+            .with_disk_cache(DiskCache::new(directory.path()));
+        let mut tiles = Tiles::with_fetcher(fetcher, TilesOptions::default(), Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+        tile_mock.assert();
+    }
+
+    #[cfg(all(feature = "mbtiles", not(target_family = "wasm")))]
+    #[test]
+    fn tiles_are_read_from_mbtiles() {
+        let _ = env_logger::try_init();
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("test.mbtiles");
+        crate::mbtiles::tests::create(&path);
+
+        let mut tiles = Tiles::with_fetcher(
+            crate::MbTiles::open(&path).unwrap(),
+            TilesOptions::default(),
+            Context::default(),
+        );
+
+        let missing = TileId { x: 2, ..TILE_ID };
+        tiles.at(missing);
+        while tiles.at(TILE_ID).is_none() {}
+
+        while tiles.state(missing) == Some(TileState::Pending) {
+            tiles.at(TILE_ID);
+        }
+        assert_eq!(Some(TileState::Missing), tiles.state(missing));
+        assert_eq!(Status::default(), tiles.status());
+    }
+
+    #[cfg(feature = "mvt")]
+    #[test]
+    fn vector_tiles_are_tessellated_according_to_style() {
+        use crate::{LayerStyle, VectorStyle};
+        use egui::{Color32, Pos2};
+
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("vector_tile.pbf"))
+            .create();
+
+        let options = TilesOptions {
+            vector_style: Some(
+                VectorStyle::default()
+                    .with_layer(LayerStyle::new("water").with_fill(Color32::BLUE))
+                    .with_layer(LayerStyle::new("places").with_label("name")),
+            ),
+            ..Default::default()
+        };
+        let ctx = Context::default();
+        let mut tiles = Tiles::with_options(source, options, ctx.clone());
+
+        let tile = loop {
+            if let Some(tile) = tiles.at(TILE_ID) {
+                break tile;
+            }
+        };
+        tile_mock.assert();
+
+        let rect = Rect::from_min_size(Pos2::new(100., 100.), egui::Vec2::splat(256.));
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.));
+        let _ = ctx.run(Default::default(), |ctx| {
+            // Mesh and the label.
+            assert_eq!(2, tile.shapes(rect, uv, ctx).len());
+        });
+
+        // Mesh is placed within the rectangle, and clipped to the part of the tile.
+        let top_left = Rect::from_min_max(Pos2::ZERO, Pos2::new(0.5, 0.5));
+        let _ = ctx.run(Default::default(), |ctx| {
+            let shapes = tile.shapes(rect, top_left, ctx);
+
+            // Town is in the top right quarter.
+            assert_eq!(1, shapes.len());
+            let Shape::Mesh(mesh) = &shapes[0] else {
+                panic!("expected a mesh");
+            };
+            assert!(!mesh.vertices.is_empty());
+            assert!(mesh
+                .vertices
+                .iter()
+                .all(|vertex| rect.expand(0.001).contains(vertex.pos)));
+        });
+    }
+
+    fn assert_tile_is_empty_forever(tiles: &mut Tiles) {
+        // Should be None now, and forever.
+        assert!(tiles.at(TILE_ID).is_none());
+        std::thread::sleep(Duration::from_secs(1));
+        assert!(tiles.at(TILE_ID).is_none());
+    }
+
+    #[test]
+    fn tile_is_empty_forever_if_http_returns_error() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let mut tiles = Tiles::new(source, Context::default());
+
+        // Not found is a permanent error, so it is not retried.
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(404)
+            .expect(1)
+            .create();
+
+        assert_tile_is_empty_forever(&mut tiles);
+        tile_mock.assert();
+    }
+
+    fn fast_retry() -> TilesOptions {
+        TilesOptions {
+            retry: Retry {
+                initial_backoff: Duration::from_millis(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+
+        // Mocks which did not receive expected number of hits take precedence.
+        let unavailable_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(503)
+            .expect(2)
+            .create();
+        let rate_limited_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(429)
+            .expect(1)
+            .create();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+
+        let mut tiles = Tiles::with_options(source, fast_retry(), Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+
+        unavailable_mock.assert();
+        rate_limited_mock.assert();
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn transient_errors_of_fetchers_are_retried() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let unavailable_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(503)
+            .expect(2)
+            .create();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+
+        let fetcher = HttpFetcher::new(source, &HttpOptions::default()).unwrap();
+        let mut tiles = Tiles::with_fetcher(fetcher, fast_retry(), Context::default());
+        while tiles.at(TILE_ID).is_none() {}
+
+        unavailable_mock.assert();
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn tiles_waiting_for_retry_do_not_block_others() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let unavailable_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(503)
+            .expect(1)
+            .create();
+        let tile_mock = server
+            .mock("GET", "/3/2/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .create();
+
+        let mut tiles = Tiles::with_options(
+            source,
+            TilesOptions {
+                parallelism: 1,
+                retry: Retry {
+                    max_retries: 1,
+                    initial_backoff: Duration::from_secs(3600),
+                    max_backoff: Duration::from_secs(3600),
+                },
+                ..Default::default()
+            },
+            Context::default(),
+        );
+
+        tiles.at(TILE_ID);
+        while !unavailable_mock.matched() {}
+
+        // The only slot is free while the first tile waits for its retry.
+        let other = TileId { x: 2, ..TILE_ID };
+        while tiles.at(other).is_none() {}
+
+        assert_eq!(Some(TileState::Pending), tiles.state(TILE_ID));
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn retries_are_limited() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(500)
+            .expect(3)
+            .create();
+
+        let mut options = fast_retry();
+        options.retry.max_retries = 2;
+        let mut tiles = Tiles::with_options(source, options, Context::default());
+
+        assert_tile_is_empty_forever(&mut tiles);
+        tile_mock.assert();
+    }
+
+    #[test]
+    fn failed_tiles_are_downloaded_again_after_clearing() {
+        let _ = env_logger::try_init();
+
+        let (mut server, source) = mockito_server();
+        let forbidden_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_status(403)
+            .expect(1)
+            .create();
+
+        let mut tiles = Tiles::new(source, Context::default());
+        assert_tile_is_empty_forever(&mut tiles);
+        forbidden_mock.assert();
+
+        let tile_mock = server
+            .mock("GET", "/3/1/2.png")
+            .with_body(include_bytes!("valid.png"))
+            .expect(1)
+            .create();
+
+        tiles.clear_failed();
+        while tiles.at(TILE_ID).is_none() {}
         tile_mock.assert();
     }
 
@@ -230,7 +1598,7 @@ mod tests {
         }
 
         fn attribution(&self) -> Attribution {
-            Attribution { text: "", url: "" }
+            Attribution::new("", "")
         }
     }
 
@@ -0,0 +1,706 @@
+//! [Mapbox Vector Tiles](https://github.com/mapbox/vector-tile-spec), tessellated into egui
+//! meshes according to a [`VectorStyle`].
+
+use std::io::Read;
+
+use egui::{Color32, Mesh, Pos2, Rect};
+use lyon_tessellation::{
+    geom::point, path::Path, BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex,
+    StrokeOptions, StrokeTessellator, StrokeVertex, VertexBuffers,
+};
+use prost::Message;
+
+use crate::mercator::{TileId, TILE_SIZE};
+
+/// Protocol buffers messages, as defined by the version 2.1 of the specification.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Tile {
+        #[prost(message, repeated, tag = "3")]
+        pub layers: Vec<Layer>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Layer {
+        #[prost(uint32, required, tag = "15")]
+        pub version: u32,
+        #[prost(string, required, tag = "1")]
+        pub name: String,
+        #[prost(message, repeated, tag = "2")]
+        pub features: Vec<Feature>,
+        #[prost(string, repeated, tag = "3")]
+        pub keys: Vec<String>,
+        #[prost(message, repeated, tag = "4")]
+        pub values: Vec<Value>,
+        #[prost(uint32, optional, tag = "5")]
+        pub extent: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Feature {
+        #[prost(uint64, optional, tag = "1")]
+        pub id: Option<u64>,
+        #[prost(uint32, repeated, tag = "2")]
+        pub tags: Vec<u32>,
+        #[prost(enumeration = "GeomType", optional, tag = "3")]
+        pub r#type: Option<i32>,
+        #[prost(uint32, repeated, tag = "4")]
+        pub geometry: Vec<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Value {
+        #[prost(string, optional, tag = "1")]
+        pub string_value: Option<String>,
+        #[prost(float, optional, tag = "2")]
+        pub float_value: Option<f32>,
+        #[prost(double, optional, tag = "3")]
+        pub double_value: Option<f64>,
+        #[prost(int64, optional, tag = "4")]
+        pub int_value: Option<i64>,
+        #[prost(uint64, optional, tag = "5")]
+        pub uint_value: Option<u64>,
+        #[prost(sint64, optional, tag = "6")]
+        pub sint_value: Option<i64>,
+        #[prost(bool, optional, tag = "7")]
+        pub bool_value: Option<bool>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum GeomType {
+        Unknown = 0,
+        Point = 1,
+        Linestring = 2,
+        Polygon = 3,
+    }
+
+    impl Value {
+        /// Value formatted for a label.
+        pub fn to_label(&self) -> Option<String> {
+            self.string_value
+                .clone()
+                .or_else(|| self.float_value.map(|value| value.to_string()))
+                .or_else(|| self.double_value.map(|value| value.to_string()))
+                .or_else(|| self.int_value.map(|value| value.to_string()))
+                .or_else(|| self.uint_value.map(|value| value.to_string()))
+                .or_else(|| self.sint_value.map(|value| value.to_string()))
+                .or_else(|| self.bool_value.map(|value| value.to_string()))
+        }
+    }
+}
+
+/// How the vector tiles are drawn. Only layers mentioned in the style are drawn, in the order
+/// they were added.
+///
+/// ```
+/// # use walkers::{LayerStyle, VectorStyle};
+/// # use egui::Color32;
+/// let style = VectorStyle::default()
+///     .with_background(Color32::from_rgb(242, 239, 233))
+///     .with_layer(LayerStyle::new("water").with_fill(Color32::from_rgb(170, 211, 223)))
+///     .with_layer(
+///         LayerStyle::new("roads")
+///             .with_line(Color32::WHITE, 1.)
+///             .with_line_width([(10, 1.), (16, 8.)])
+///             .with_label("name"),
+///     );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct VectorStyle {
+    background: Option<Color32>,
+    layers: Vec<LayerStyle>,
+}
+
+impl VectorStyle {
+    /// Color filling the whole tile, below all the layers.
+    pub fn with_background(mut self, color: Color32) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    /// Draw features of a layer. Same layer can be added several times, e.g. to draw road
+    /// casings below the roads.
+    pub fn with_layer(mut self, layer: LayerStyle) -> Self {
+        self.layers.push(layer);
+        self
+    }
+}
+
+/// How features of a single layer of the vector tile are drawn.
+#[derive(Clone, Debug)]
+pub struct LayerStyle {
+    source_layer: String,
+    fill: Option<Color32>,
+    line: Option<Color32>,
+
+    /// Width in pixels, by zoom level. Interpolated linearly between the stops.
+    line_width: Vec<(u8, f32)>,
+    label: Option<String>,
+    label_color: Color32,
+    label_size: f32,
+}
+
+impl LayerStyle {
+    /// Style of the layer with given name. Nothing is drawn until a fill, line or label is set.
+    pub fn new(source_layer: impl Into<String>) -> Self {
+        Self {
+            source_layer: source_layer.into(),
+            fill: None,
+            line: None,
+            line_width: vec![(0, 1.)],
+            label: None,
+            label_color: Color32::BLACK,
+            label_size: 12.,
+        }
+    }
+
+    /// Fill polygons with given color.
+    pub fn with_fill(mut self, color: Color32) -> Self {
+        self.fill = Some(color);
+        self
+    }
+
+    /// Stroke lines and polygon outlines with given color and width (in pixels).
+    pub fn with_line(mut self, color: Color32, width: f32) -> Self {
+        self.line = Some(color);
+        self.line_width = vec![(0, width)];
+        self
+    }
+
+    /// Line width depending on zoom level, given as `(zoom, width)` stops. Width is interpolated
+    /// linearly between them, and kept constant below the first and above the last one.
+    pub fn with_line_width(mut self, stops: impl IntoIterator<Item = (u8, f32)>) -> Self {
+        let mut stops: Vec<_> = stops.into_iter().collect();
+        stops.sort_by_key(|(zoom, _)| *zoom);
+        if !stops.is_empty() {
+            self.line_width = stops;
+        }
+        self
+    }
+
+    /// Label features with the value of given field.
+    pub fn with_label(mut self, field: impl Into<String>) -> Self {
+        self.label = Some(field.into());
+        self
+    }
+
+    pub fn with_label_color(mut self, color: Color32) -> Self {
+        self.label_color = color;
+        self
+    }
+
+    pub fn with_label_size(mut self, size: f32) -> Self {
+        self.label_size = size;
+        self
+    }
+
+    fn line_width_at(&self, zoom: u8) -> f32 {
+        let after = self.line_width.iter().position(|(stop, _)| *stop > zoom);
+        match after {
+            Some(0) | None => {
+                let index = after.unwrap_or(self.line_width.len() - 1);
+                self.line_width[index].1
+            }
+            Some(after) => {
+                let (zoom_0, width_0) = self.line_width[after - 1];
+                let (zoom_1, width_1) = self.line_width[after];
+                let t = (zoom - zoom_0) as f32 / (zoom_1 - zoom_0) as f32;
+                width_0 + (width_1 - width_0) * t
+            }
+        }
+    }
+}
+
+/// Text drawn over the tile.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Label {
+    /// Within the tile, from (0, 0) to (1, 1).
+    pub position: Pos2,
+    pub text: String,
+    pub color: Color32,
+    pub size: f32,
+}
+
+/// Decoded and tessellated vector tile.
+pub(crate) struct VectorTile {
+    /// Untextured mesh, with positions within the tile, from (0, 0) to (1, 1).
+    pub mesh: Mesh,
+    pub labels: Vec<Label>,
+}
+
+/// How many zoom levels lower tiles of given size (in pixels) are taken from, e.g. 1 for 512 pixel
+/// tiles, so that they are drawn at their natural size. Vector tiles always follow the Web
+/// Mercator levels, and so do the zooms of the [`VectorStyle`].
+fn tile_zoom_offset(tile_size: u32) -> u8 {
+    (tile_size / TILE_SIZE).max(1).ilog2() as u8
+}
+
+impl VectorTile {
+    /// Decode the tile (possibly gzipped, as stored in MBTiles) and tessellate it. `tile_size` is
+    /// the size (in pixels) the tile is drawn at, at its zoom level.
+    pub fn decode(
+        bytes: &[u8],
+        tile_id: TileId,
+        tile_size: u32,
+        style: &VectorStyle,
+    ) -> Result<Self, String> {
+        let mut decompressed = Vec::new();
+        let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+            flate2::read::GzDecoder::new(bytes)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| e.to_string())?;
+            &decompressed
+        } else {
+            bytes
+        };
+
+        let tile = proto::Tile::decode(bytes).map_err(|e| e.to_string())?;
+        // Bigger tiles are drawn at higher zoom levels.
+        let zoom = tile_id.zoom.saturating_add(tile_zoom_offset(tile_size));
+        Ok(tessellate(&tile, zoom, tile_size as f32, style))
+    }
+
+    /// Approximate amount of memory taken by the tile.
+    pub fn size_in_bytes(&self) -> usize {
+        self.mesh.vertices.len() * std::mem::size_of::<egui::epaint::Vertex>()
+            + self.mesh.indices.len() * std::mem::size_of::<u32>()
+            + self
+                .labels
+                .iter()
+                .map(|label| std::mem::size_of::<Label>() + label.text.len())
+                .sum::<usize>()
+    }
+}
+
+/// Features' geometry, as lines (or rings), in tile's coordinates from (0, 0) to (1, 1).
+fn geometry(commands: &[u32], extent: f32) -> Vec<Vec<Pos2>> {
+    const MOVE_TO: u32 = 1;
+    const LINE_TO: u32 = 2;
+    const CLOSE_PATH: u32 = 7;
+
+    let zigzag = |value: u32| ((value >> 1) as i32) ^ -((value & 1) as i32);
+
+    let mut parts: Vec<Vec<Pos2>> = Vec::new();
+    let (mut x, mut y) = (0i32, 0i32);
+    let mut commands = commands.iter();
+
+    while let Some(command) = commands.next() {
+        let (id, count) = (command & 0x7, command >> 3);
+        for _ in 0..count {
+            match id {
+                MOVE_TO | LINE_TO => {
+                    let (Some(dx), Some(dy)) = (commands.next(), commands.next()) else {
+                        return parts;
+                    };
+                    x = x.wrapping_add(zigzag(*dx));
+                    y = y.wrapping_add(zigzag(*dy));
+
+                    let position = Pos2::new(x as f32 / extent, y as f32 / extent);
+                    match parts.last_mut() {
+                        Some(part) if id == LINE_TO => part.push(position),
+                        _ => parts.push(vec![position]),
+                    }
+                }
+                CLOSE_PATH => {
+                    if let Some(part) = parts.last_mut() {
+                        if let Some(first) = part.first().copied() {
+                            part.push(first);
+                        }
+                    }
+                }
+                _ => {
+                    log::warn!("Unknown vector tile geometry command: {}.", id);
+                    return parts;
+                }
+            }
+        }
+    }
+
+    parts
+}
+
+fn path(parts: &[Vec<Pos2>]) -> Path {
+    let mut builder = Path::builder();
+    for part in parts {
+        let mut points = part.iter().map(|p| point(p.x, p.y));
+        if let Some(first) = points.next() {
+            builder.begin(first);
+            for p in points {
+                builder.line_to(p);
+            }
+            builder.end(false);
+        }
+    }
+    builder.build()
+}
+
+/// Where the feature's label goes, if anywhere.
+fn label_position(geom_type: proto::GeomType, parts: &[Vec<Pos2>]) -> Option<Pos2> {
+    let first = parts.first()?;
+    let position = match geom_type {
+        proto::GeomType::Point => *first.first()?,
+        proto::GeomType::Linestring => *first.get(first.len() / 2)?,
+        proto::GeomType::Polygon => {
+            let sum = first
+                .iter()
+                .fold(egui::Vec2::ZERO, |sum, p| sum + p.to_vec2());
+            (sum / first.len() as f32).to_pos2()
+        }
+        proto::GeomType::Unknown => return None,
+    };
+
+    // Features reaching into tile's buffer are labeled by their own tile.
+    let tile = Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.));
+    (tile.contains(position) && position.x < 1. && position.y < 1.).then_some(position)
+}
+
+fn tessellate(tile: &proto::Tile, zoom: u8, tile_size: f32, style: &VectorStyle) -> VectorTile {
+    let mut buffers: VertexBuffers<(Pos2, Color32), u32> = VertexBuffers::new();
+    let mut labels = Vec::new();
+
+    let mut fill_tessellator = FillTessellator::new();
+    let mut stroke_tessellator = StrokeTessellator::new();
+
+    // Tessellation happens in tile's coordinates, so the tolerance is a fraction of a pixel.
+    let tolerance = 0.1 / tile_size;
+
+    if let Some(background) = style.background {
+        let rect = Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.));
+        let base = buffers.vertices.len() as u32;
+        for corner in [
+            rect.left_top(),
+            rect.right_top(),
+            rect.right_bottom(),
+            rect.left_bottom(),
+        ] {
+            buffers.vertices.push((corner, background));
+        }
+        buffers
+            .indices
+            .extend([0, 1, 2, 0, 2, 3].map(|index| base + index));
+    }
+
+    for layer_style in &style.layers {
+        let Some(layer) = tile
+            .layers
+            .iter()
+            .find(|layer| layer.name == layer_style.source_layer)
+        else {
+            continue;
+        };
+
+        let extent = layer.extent.unwrap_or(4096).max(1) as f32;
+        let line_width = layer_style.line_width_at(zoom) / tile_size;
+
+        for feature in &layer.features {
+            let geom_type = feature
+                .r#type
+                .and_then(|t| proto::GeomType::try_from(t).ok())
+                .unwrap_or(proto::GeomType::Unknown);
+            let parts = geometry(&feature.geometry, extent);
+
+            if geom_type == proto::GeomType::Polygon {
+                if let Some(color) = layer_style.fill {
+                    // Holes are wound the other way, and islands within them the same way as
+                    // exterior rings, so both fill rules work.
+                    let result = fill_tessellator.tessellate_path(
+                        &path(&parts),
+                        &FillOptions::tolerance(tolerance).with_fill_rule(FillRule::NonZero),
+                        &mut BuffersBuilder::new(&mut buffers, |vertex: FillVertex| {
+                            let p = vertex.position();
+                            (Pos2::new(p.x, p.y), color)
+                        }),
+                    );
+                    if let Err(e) = result {
+                        log::debug!("Could not fill a feature of '{}': {:?}", layer.name, e);
+                    }
+                }
+            }
+
+            if matches!(
+                geom_type,
+                proto::GeomType::Linestring | proto::GeomType::Polygon
+            ) {
+                if let Some(color) = layer_style.line {
+                    let result = stroke_tessellator.tessellate_path(
+                        &path(&parts),
+                        &StrokeOptions::tolerance(tolerance).with_line_width(line_width),
+                        &mut BuffersBuilder::new(&mut buffers, |vertex: StrokeVertex| {
+                            let p = vertex.position();
+                            (Pos2::new(p.x, p.y), color)
+                        }),
+                    );
+                    if let Err(e) = result {
+                        log::debug!("Could not stroke a feature of '{}': {:?}", layer.name, e);
+                    }
+                }
+            }
+
+            if let Some(field) = &layer_style.label {
+                let text = feature
+                    .tags
+                    .chunks_exact(2)
+                    .find(|tag| layer.keys.get(tag[0] as usize) == Some(field))
+                    .and_then(|tag| layer.values.get(tag[1] as usize))
+                    .and_then(proto::Value::to_label);
+
+                if let (Some(text), Some(position)) = (text, label_position(geom_type, &parts)) {
+                    labels.push(Label {
+                        position,
+                        text,
+                        color: layer_style.label_color,
+                        size: layer_style.label_size,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut mesh = Mesh::default();
+    for (position, color) in buffers.vertices {
+        mesh.colored_vertex(position, color);
+    }
+    mesh.indices = buffers.indices;
+
+    // Features reach into the tile's buffer, which is covered by the neighbouring tiles.
+    VectorTile {
+        mesh: clip(&mesh, Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.))),
+        labels,
+    }
+}
+
+/// Part of the mesh within the rectangle. Triangles crossing its edges are cut.
+pub(crate) fn clip(mesh: &Mesh, rect: Rect) -> Mesh {
+    let mut clipped = Mesh::with_texture(mesh.texture_id);
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let vertices = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize]);
+
+        if vertices.iter().all(|vertex| rect.contains(vertex.pos)) {
+            let base = clipped.vertices.len() as u32;
+            clipped.vertices.extend(vertices);
+            clipped.indices.extend([base, base + 1, base + 2]);
+            continue;
+        }
+
+        // Sutherland–Hodgman, against each edge of the rectangle in turn. Colors are the same
+        // for all vertices of a triangle, as features are filled with a single color.
+        let mut polygon: Vec<Pos2> = vertices.iter().map(|vertex| vertex.pos).collect();
+        // Axis (0 for x, 1 for y), position of the edge, and whether it is the minimum.
+        let edges = [
+            (0, rect.min.x, true),
+            (0, rect.max.x, false),
+            (1, rect.min.y, true),
+            (1, rect.max.y, false),
+        ];
+
+        for (axis, limit, is_min) in edges {
+            let axis = |p: Pos2| p[axis];
+            let inside = |p: Pos2| {
+                if is_min {
+                    axis(p) >= limit
+                } else {
+                    axis(p) <= limit
+                }
+            };
+
+            let mut output = Vec::with_capacity(polygon.len() + 2);
+            for (i, current) in polygon.iter().copied().enumerate() {
+                let previous = polygon[(i + polygon.len() - 1) % polygon.len()];
+                if inside(current) != inside(previous) {
+                    let t = (limit - axis(previous)) / (axis(current) - axis(previous));
+                    output.push(previous + (current - previous) * t);
+                }
+                if inside(current) {
+                    output.push(current);
+                }
+            }
+
+            polygon = output;
+            if polygon.is_empty() {
+                break;
+            }
+        }
+
+        if polygon.len() >= 3 {
+            let base = clipped.vertices.len() as u32;
+            for position in polygon.iter().copied() {
+                clipped.colored_vertex(position, vertices[0].color);
+            }
+            for i in 1..polygon.len() as u32 - 1 {
+                clipped.add_triangle(base, base + i, base + i + 1);
+            }
+        }
+    }
+
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TILE_ID: TileId = TileId {
+        x: 0,
+        y: 0,
+        zoom: 12,
+    };
+
+    #[test]
+    fn tile_zoom_offset_depends_on_tile_size() {
+        assert_eq!(0, tile_zoom_offset(256));
+        assert_eq!(1, tile_zoom_offset(512));
+        assert_eq!(2, tile_zoom_offset(1024));
+
+        // Smaller tiles are just upscaled.
+        assert_eq!(0, tile_zoom_offset(128));
+    }
+
+    fn style() -> VectorStyle {
+        VectorStyle::default()
+            .with_layer(LayerStyle::new("water").with_fill(Color32::BLUE))
+            .with_layer(
+                LayerStyle::new("roads")
+                    .with_line(Color32::WHITE, 4.)
+                    .with_label("name"),
+            )
+            .with_layer(LayerStyle::new("places").with_label("name"))
+    }
+
+    /// Area covered by the mesh's triangles, in tile's coordinates.
+    fn area(mesh: &Mesh, color: Color32) -> f32 {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|triangle| triangle.iter().map(|i| mesh.vertices[*i as usize]))
+            .filter(|vertices| vertices.clone().all(|vertex| vertex.color == color))
+            .map(|mut vertices| {
+                let (a, b, c) = (
+                    vertices.next().unwrap().pos,
+                    vertices.next().unwrap().pos,
+                    vertices.next().unwrap().pos,
+                );
+                ((b - a).x * (c - a).y - (c - a).x * (b - a).y).abs() / 2.
+            })
+            .sum()
+    }
+
+    #[test]
+    fn decoding_geometry() {
+        // Example from the specification.
+        assert_eq!(
+            vec![vec![
+                Pos2::new(2., 2.),
+                Pos2::new(2., 10.),
+                Pos2::new(10., 10.)
+            ]],
+            geometry(&[9, 4, 4, 18, 0, 16, 16, 0], 1.)
+        );
+
+        // Two points, and a closed ring.
+        assert_eq!(
+            vec![vec![Pos2::new(5., 7.)], vec![Pos2::new(3., 2.)]],
+            geometry(&[17, 10, 14, 3, 9], 1.)
+        );
+        assert_eq!(
+            vec![vec![
+                Pos2::new(3., 6.),
+                Pos2::new(8., 12.),
+                Pos2::new(20., 34.),
+                Pos2::new(3., 6.)
+            ]],
+            geometry(&[9, 6, 12, 18, 10, 12, 24, 44, 15], 1.)
+        );
+    }
+
+    #[test]
+    fn tessellating_according_to_style() {
+        let tile =
+            VectorTile::decode(include_bytes!("vector_tile.pbf"), TILE_ID, 256, &style()).unwrap();
+
+        // West half of the tile, minus the hole.
+        approx::assert_relative_eq!(
+            0.5 - 0.125 * 0.125,
+            area(&tile.mesh, Color32::BLUE),
+            max_relative = 0.001
+        );
+
+        // Road is clipped to the tile, and 4 pixels wide.
+        approx::assert_relative_eq!(
+            4. / 256.,
+            area(&tile.mesh, Color32::WHITE),
+            max_relative = 0.01
+        );
+        assert!(tile.mesh.vertices.iter().all(|vertex| Rect::from_min_max(
+            Pos2::ZERO,
+            Pos2::new(1., 1.)
+        )
+        .contains(vertex.pos)));
+
+        // Place in the buffer is not labeled.
+        assert_eq!(
+            vec!["Main Street", "Town"],
+            tile.labels
+                .iter()
+                .map(|label| label.text.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Pos2::new(0.75, 0.25), tile.labels[1].position);
+    }
+
+    #[test]
+    fn layers_not_in_style_are_not_drawn() {
+        let style = VectorStyle::default().with_layer(LayerStyle::new("places"));
+        let tile =
+            VectorTile::decode(include_bytes!("vector_tile.pbf"), TILE_ID, 256, &style).unwrap();
+        assert!(tile.mesh.vertices.is_empty());
+        assert!(tile.labels.is_empty());
+    }
+
+    #[test]
+    fn gzipped_tiles() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        std::io::Write::write_all(&mut encoder, include_bytes!("vector_tile.pbf")).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let tile = VectorTile::decode(&gzipped, TILE_ID, 256, &style()).unwrap();
+        assert_eq!(2, tile.labels.len());
+
+        assert!(VectorTile::decode(b"garbage", TILE_ID, 256, &style()).is_err());
+    }
+
+    #[test]
+    fn line_width_by_zoom() {
+        let style = LayerStyle::new("roads").with_line_width([(16, 8.), (10, 2.)]);
+        assert_eq!(2., style.line_width_at(5));
+        assert_eq!(2., style.line_width_at(10));
+        assert_eq!(5., style.line_width_at(13));
+        assert_eq!(8., style.line_width_at(16));
+        assert_eq!(8., style.line_width_at(20));
+
+        assert_eq!(
+            3.,
+            LayerStyle::new("roads")
+                .with_line(Color32::RED, 3.)
+                .line_width_at(7)
+        );
+    }
+
+    #[test]
+    fn clipping_triangles() {
+        let mut mesh = Mesh::default();
+        mesh.colored_vertex(Pos2::new(0., 0.), Color32::RED);
+        mesh.colored_vertex(Pos2::new(2., 0.), Color32::RED);
+        mesh.colored_vertex(Pos2::new(0., 2.), Color32::RED);
+        mesh.add_triangle(0, 1, 2);
+
+        let clipped = clip(&mesh, Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.)));
+        approx::assert_relative_eq!(1., area(&clipped, Color32::RED));
+
+        let clipped = clip(
+            &mesh,
+            Rect::from_min_max(Pos2::new(5., 5.), Pos2::new(6., 6.)),
+        );
+        assert!(clipped.indices.is_empty());
+    }
+}
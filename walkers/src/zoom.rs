@@ -19,6 +19,12 @@ impl TryFrom<f32> for Zoom {
     }
 }
 
+impl From<Zoom> for f64 {
+    fn from(zoom: Zoom) -> Self {
+        zoom.0 as f64
+    }
+}
+
 impl Default for Zoom {
     fn default() -> Self {
         Self(16.)
@@ -26,6 +32,7 @@ impl Default for Zoom {
 }
 
 impl Zoom {
+    /// Nearest integer zoom level, such as the one used by the tiles.
     pub fn round(&self) -> u8 {
         self.0.round() as u8
     }